@@ -1,27 +1,519 @@
 set client_encoding = 'UTF8';
 
+-- 既存の DB に対して何度実行してもよいように書く。古いスキーマの DB は ALTER で今のスキーマに揃える
+
+-- gongzuo の期間の重複を排他制約で防ぐのに使う
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
 CREATE TABLE IF NOT EXISTS users (
     id SERIAL PRIMARY KEY,
     username VARCHAR(255) NOT NULL UNIQUE,
-    salt VARCHAR(255) NOT NULL,
+    -- 旧形式 (PBKDF2) のハッシュの salt (hex)。PHC 文字列は salt を含むので空
+    salt VARCHAR(255) NOT NULL DEFAULT '',
+    -- Argon2id の PHC 文字列。旧形式の PBKDF2 (hex) はログイン時に置き換えられる
     password VARCHAR(255) NOT NULL,
     created_at TIMESTAMP NOT NULL DEFAULT NOW(),
     is_admin BOOLEAN NOT NULL DEFAULT FALSE,
-    session_token VARCHAR(255) UNIQUE
+    -- IANA time zone name. 日時の表示や日の境界の計算に使う
+    time_zone VARCHAR(64) NOT NULL DEFAULT 'Asia/Tokyo',
+    -- 他のユーザーにこのユーザーの gongzuo を見せる範囲。admin はいつでも全部見られる
+    -- 'private': 本人だけ, 'team': 同じプロジェクトかチームのメンバーまで, 'public': 全員
+    visibility VARCHAR(16) NOT NULL DEFAULT 'team',
+    -- NULL なら有効。無効化されたユーザーはログインできない
+    deactivated_at TIMESTAMP,
+    -- admin がパスワードをリセットしたら、本人が変更するまで TRUE
+    password_reset_required BOOLEAN NOT NULL DEFAULT FALSE,
+    CHECK (visibility IN ('private', 'team', 'public'))
+);
+
+-- PHC 文字列で保存するユーザーは salt を持たない
+ALTER TABLE users ALTER COLUMN salt SET DEFAULT '';
+-- 以前の users にはなかった列
+ALTER TABLE users ADD COLUMN IF NOT EXISTS time_zone VARCHAR(64) NOT NULL DEFAULT 'Asia/Tokyo';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS visibility VARCHAR(16) NOT NULL DEFAULT 'team'
+    CHECK (visibility IN ('private', 'team', 'public'));
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deactivated_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_reset_required BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id SERIAL PRIMARY KEY,
+    token VARCHAR(255) NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL,
+    last_seen_at TIMESTAMP NOT NULL,
+    -- 最後のアクセスから SESSION_TTL が経過すると失効する
+    expires_at TIMESTAMP NOT NULL,
+    user_agent VARCHAR(1023),
+    ip VARCHAR(255)
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions (user_id);
+
+-- 以前は users.session_token にセッションを1つだけ持っていた。今ログインしている状態のまま sessions に移す
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'users' AND column_name = 'session_token'
+    ) THEN
+        INSERT INTO sessions (token, user_id, created_at, last_seen_at, expires_at)
+        SELECT
+            session_token,
+            id,
+            NOW() AT TIME ZONE 'UTC',
+            NOW() AT TIME ZONE 'UTC',
+            NOW() AT TIME ZONE 'UTC' + INTERVAL '30 days'
+        FROM users
+        WHERE session_token IS NOT NULL
+        ON CONFLICT (token) DO NOTHING;
+
+        ALTER TABLE users DROP COLUMN session_token;
+    END IF;
+END
+$$;
+
+-- is_admin のユーザーはロールに関係なくすべての権限を持つ
+CREATE TABLE IF NOT EXISTS roles (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role_id INTEGER NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+    permission VARCHAR(64) NOT NULL,
+    PRIMARY KEY (role_id, permission),
+    CHECK (permission IN ('users.create', 'gongzuo.edit_others', 'reports.view_team', 'timesheets.approve'))
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role_id INTEGER NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, role_id)
+);
+
+CREATE INDEX IF NOT EXISTS user_roles_role_id_idx ON user_roles (role_id);
+
+INSERT INTO roles (name)
+VALUES
+    ('manager')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_id, permission)
+SELECT roles.id, permissions.permission
+FROM roles, (VALUES ('gongzuo.edit_others'), ('reports.view_team'), ('timesheets.approve')) AS permissions (permission)
+WHERE roles.name = 'manager'
+ON CONFLICT DO NOTHING;
+
+-- admin が発行するパスワードリセット用の使い捨てトークン。トークンそのものは保存せず、SHA-256 だけを持つ
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    -- 一度使われたら NULL ではなくなり、二度と使えない
+    used_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS password_reset_tokens_user_id_idx ON password_reset_tokens (user_id);
+
+-- TOTP (RFC 6238) による二要素認証。コードの計算に必要なので secret は平文で持つ
+-- confirmed_at が NULL の間は登録中で、ログインには使われない
+CREATE TABLE IF NOT EXISTS totp_credentials (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    -- Base32
+    secret VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    confirmed_at TIMESTAMP,
+    -- 最後に使われたコードの time step。同じコードは二度使えない
+    last_used_step BIGINT
 );
 
+-- 認証アプリを使えないときのための使い捨てのコード。SHA-256 だけを持つ
+CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMP,
+    UNIQUE (user_id, code_hash)
+);
+
+-- パスワードは正しかったが、まだ TOTP のコードを確かめていないログイン。SHA-256 だけを持つ
+CREATE TABLE IF NOT EXISTS login_challenges (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
+
+-- ログインの失敗回数。ユーザー名ごとと IP アドレスごとに数え、続けて失敗するとしばらくログインできなくなる
+-- ユーザー名は存在しなくても数える (存在するかどうかを漏らさないため)
+CREATE TABLE IF NOT EXISTS login_failures (
+    -- 'username' か 'ip'
+    scope VARCHAR(16) NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    failed_count INTEGER NOT NULL,
+    last_failed_at TIMESTAMP NOT NULL,
+    -- これより前はパスワードを確かめずに拒否する
+    locked_until TIMESTAMP,
+    PRIMARY KEY (scope, key),
+    CHECK (scope IN ('username', 'ip'))
+);
+
+-- スクリプトや連携ツールが使う個人用のアクセストークン。トークンそのものは保存せず、SHA-256 だけを持つ
+CREATE TABLE IF NOT EXISTS access_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL,
+    -- まだ使われていなければ NULL
+    last_used_at TIMESTAMP,
+    UNIQUE (user_id, name)
+);
+
+-- トークンで呼べる API の範囲。スコープに含まれない API は 403 になる
+CREATE TABLE IF NOT EXISTS access_token_scopes (
+    access_token_id INTEGER NOT NULL REFERENCES access_tokens(id) ON DELETE CASCADE,
+    scope VARCHAR(64) NOT NULL,
+    PRIMARY KEY (access_token_id, scope),
+    CHECK (scope IN ('gongzuo:read', 'gongzuo:write', 'reports:read'))
+);
+
+-- admin によるユーザー管理と、パスワード変更の記録
+CREATE TABLE IF NOT EXISTS audit_logs (
+    id SERIAL PRIMARY KEY,
+    -- 操作したユーザー
+    actor_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    -- 'user.create', 'user.deactivate' など
+    action VARCHAR(64) NOT NULL,
+    target_user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_logs_target_user_id_idx ON audit_logs (target_user_id);
+
+CREATE TABLE IF NOT EXISTS teams (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL
+);
+
+-- owner はチームの変更・削除とロールの変更ができる。manager はメンバーの招待と削除ができる
+-- チームには owner が必ず1人以上いる
+CREATE TABLE IF NOT EXISTS team_memberships (
+    team_id INTEGER NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(16) NOT NULL,
+    joined_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (team_id, user_id),
+    CHECK (role IN ('owner', 'manager', 'member'))
+);
+
+CREATE INDEX IF NOT EXISTS team_memberships_user_id_idx ON team_memberships (user_id);
+
+-- 承諾・辞退・取り消しされたら消す
+CREATE TABLE IF NOT EXISTS team_invitations (
+    id SERIAL PRIMARY KEY,
+    team_id INTEGER NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- owner としては招待できない。メンバーになってから owner がロールを変える
+    role VARCHAR(16) NOT NULL,
+    invited_by INTEGER NOT NULL REFERENCES users(id),
+    created_at TIMESTAMP NOT NULL,
+    UNIQUE (team_id, user_id),
+    CHECK (role IN ('manager', 'member'))
+);
+
+CREATE TABLE IF NOT EXISTS categories (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    -- '#rrggbb'
+    color VARCHAR(7) NOT NULL DEFAULT '#808080',
+    -- 仕事として集計するかどうか
+    counts_as_work BOOLEAN NOT NULL DEFAULT TRUE,
+    -- archive されたカテゴリーは新しい gongzuo には使えない
+    archived BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+-- 以前の content_kind (0: 仕事, 1: 仕事以外) と同じ id で登録しておく
+INSERT INTO categories (id, name, color, counts_as_work)
+VALUES
+    (0, 'Work', '#4caf50', TRUE),
+    (1, 'NotWork', '#9e9e9e', FALSE)
+ON CONFLICT (id) DO NOTHING;
+
+SELECT setval(pg_get_serial_sequence('categories', 'id'), (SELECT MAX(id) FROM categories));
+
 CREATE TABLE IF NOT EXISTS contents (
     id SERIAL PRIMARY KEY,
-    -- 0: 仕事, 1: 仕事以外
-    content_kind INTEGER NOT NULL CHECK (content_kind IN (0, 1)),
+    category_id INTEGER NOT NULL REFERENCES categories(id),
     content VARCHAR(1023) NOT NULL
 );
 
+-- 以前の contents は content_kind (0: 仕事, 1: 仕事以外) を持っていた。同じ id で登録したカテゴリーに移す
+ALTER TABLE contents ADD COLUMN IF NOT EXISTS category_id INTEGER REFERENCES categories(id);
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'contents' AND column_name = 'content_kind'
+    ) THEN
+        UPDATE contents SET category_id = content_kind WHERE category_id IS NULL;
+
+        -- CHECK (content_kind IN (0, 1)) も一緒に消える
+        ALTER TABLE contents DROP COLUMN content_kind;
+    END IF;
+END
+$$;
+
+ALTER TABLE contents ALTER COLUMN category_id SET NOT NULL;
+
+CREATE TABLE IF NOT EXISTS clients (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    archived BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE IF NOT EXISTS projects (
+    id SERIAL PRIMARY KEY,
+    client_id INTEGER NOT NULL REFERENCES clients(id),
+    name VARCHAR(255) NOT NULL,
+    -- archive されたプロジェクトは新しい gongzuo には使えない
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    UNIQUE (client_id, name)
+);
+
+-- プロジェクトのメンバーだけがそのプロジェクトの gongzuo を記録できる
+CREATE TABLE IF NOT EXISTS project_members (
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (project_id, user_id)
+);
+
+-- 時間単価。金額は通貨の最小単位 (JPY なら円) で持つ
+-- project_id と user_id の両方があればそのペア、片方だけならそのプロジェクト / ユーザーの単価
+CREATE TABLE IF NOT EXISTS billable_rates (
+    id SERIAL PRIMARY KEY,
+    project_id INTEGER REFERENCES projects(id) ON DELETE CASCADE,
+    user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    hourly_rate BIGINT NOT NULL CHECK (hourly_rate >= 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'JPY',
+    -- この日から次の単価の effective_from の前日まで有効
+    effective_from DATE NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (project_id IS NOT NULL OR user_id IS NOT NULL),
+    UNIQUE NULLS NOT DISTINCT (project_id, user_id, effective_from)
+);
+
+-- 発行済みの請求書は変更しない
+CREATE TABLE IF NOT EXISTS invoices (
+    id SERIAL PRIMARY KEY,
+    client_id INTEGER NOT NULL REFERENCES clients(id),
+    -- 期間は time_zone での日付 (両端を含む)
+    from_date DATE NOT NULL,
+    to_date DATE NOT NULL,
+    time_zone VARCHAR(64) NOT NULL,
+    rounding_minutes INTEGER NOT NULL CHECK (rounding_minutes > 0),
+    -- 'up', 'down' or 'nearest'
+    rounding VARCHAR(16) NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+    total_amount BIGINT NOT NULL,
+    issued_by INTEGER NOT NULL REFERENCES users(id),
+    issued_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
 CREATE TABLE IF NOT EXISTS gongzuo (
     id SERIAL PRIMARY KEY,
     user_id SERIAL NOT NULL REFERENCES users(id),
     content_id SERIAL NOT NULL REFERENCES contents(id),
+    -- NULL: どのプロジェクトにも属さない
+    project_id INTEGER REFERENCES projects(id),
+    -- NULL: まだ請求していない。請求済みの gongzuo は編集も削除もできない
+    invoice_id INTEGER REFERENCES invoices(id),
     started_at TIMESTAMP NOT NULL,
      -- NULL: not ended yet
-    ended_at TIMESTAMP
-);
\ No newline at end of file
+    ended_at TIMESTAMP,
+    -- NULL: ゴミ箱に入っていない。ゴミ箱の gongzuo は保持期間が過ぎたら完全に削除する
+    deleted_at TIMESTAMP,
+    CHECK (ended_at IS NULL OR ended_at > started_at),
+    -- 同じユーザーの gongzuo の期間は重ならない。ended_at が NULL なら終わりのない期間として扱う
+    -- ゴミ箱の gongzuo は重なってもよい。戻すときにもう一度確かめる
+    EXCLUDE USING gist (user_id WITH =, tsrange(started_at, ended_at) WITH &&) WHERE (deleted_at IS NULL)
+);
+
+-- 以前の gongzuo にはなかった列
+ALTER TABLE gongzuo ADD COLUMN IF NOT EXISTS project_id INTEGER REFERENCES projects(id);
+ALTER TABLE gongzuo ADD COLUMN IF NOT EXISTS invoice_id INTEGER REFERENCES invoices(id);
+ALTER TABLE gongzuo ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP;
+
+-- 以前の gongzuo には期間の制約がなかった。制約に反する gongzuo があれば、どれを直せばよいか
+-- id を並べて止める。データは勝手には直さない
+DO $$
+DECLARE
+    conflicting_ids TEXT;
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conrelid = 'gongzuo'::regclass AND conname = 'gongzuo_check') THEN
+        SELECT string_agg(id::TEXT, ', ' ORDER BY id) INTO conflicting_ids
+        FROM gongzuo
+        WHERE ended_at <= started_at;
+        IF conflicting_ids IS NOT NULL THEN
+            RAISE EXCEPTION 'gongzuo ended at or before it started: %', conflicting_ids
+                USING HINT = 'Fix ended_at of these gongzuos, then run this script again';
+        END IF;
+        ALTER TABLE gongzuo ADD CONSTRAINT gongzuo_check CHECK (ended_at IS NULL OR ended_at > started_at);
+    END IF;
+    -- ゴミ箱の gongzuo を除くようになる前の制約は作り直す
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'gongzuo'::regclass AND conname = 'gongzuo_user_id_tsrange_excl'
+        AND pg_get_constraintdef(oid) LIKE '%deleted_at IS NULL%'
+    ) THEN
+        SELECT string_agg(earlier.id || ' and ' || later.id, ', ' ORDER BY earlier.id, later.id) INTO conflicting_ids
+        FROM gongzuo AS earlier
+        JOIN gongzuo AS later
+        ON earlier.user_id = later.user_id AND earlier.id < later.id
+        WHERE earlier.deleted_at IS NULL
+        AND later.deleted_at IS NULL
+        AND tsrange(earlier.started_at, earlier.ended_at) && tsrange(later.started_at, later.ended_at);
+        IF conflicting_ids IS NOT NULL THEN
+            RAISE EXCEPTION 'gongzuo of the same user overlap: %', conflicting_ids
+                USING HINT = 'Fix or delete one of each pair, then run this script again';
+        END IF;
+        ALTER TABLE gongzuo DROP CONSTRAINT IF EXISTS gongzuo_user_id_tsrange_excl;
+        ALTER TABLE gongzuo ADD CONSTRAINT gongzuo_user_id_tsrange_excl
+            EXCLUDE USING gist (user_id WITH =, tsrange(started_at, ended_at) WITH &&) WHERE (deleted_at IS NULL);
+    END IF;
+END
+$$;
+
+-- gongzuo の変更履歴。追記のみで、更新も削除もしない
+-- gongzuo が完全に削除された後も残すので、gongzuo への外部キーは張らない
+CREATE TABLE IF NOT EXISTS gongzuo_revisions (
+    id SERIAL PRIMARY KEY,
+    gongzuo_id INTEGER NOT NULL,
+    -- 変更したユーザー
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    -- 'create', 'update', 'delete', 'restore', 'pause', 'resume'
+    action VARCHAR(16) NOT NULL,
+    revised_at TIMESTAMP NOT NULL,
+    -- 変更前と変更後の値。create では before が、delete では after が NULL
+    before JSONB,
+    after JSONB
+);
+
+CREATE INDEX IF NOT EXISTS gongzuo_revisions_gongzuo_id_idx ON gongzuo_revisions (gongzuo_id);
+
+-- gongzuo 中の休憩。休憩の時間は gongzuo の時間から引く
+CREATE TABLE IF NOT EXISTS gongzuo_breaks (
+    id SERIAL PRIMARY KEY,
+    gongzuo_id INTEGER NOT NULL REFERENCES gongzuo(id) ON DELETE CASCADE,
+    started_at TIMESTAMP NOT NULL,
+    -- NULL: 休憩中
+    ended_at TIMESTAMP,
+    CHECK (ended_at IS NULL OR ended_at > started_at),
+    EXCLUDE USING gist (gongzuo_id WITH =, tsrange(started_at, ended_at) WITH &&)
+);
+
+CREATE TABLE IF NOT EXISTS tags (
+    id SERIAL PRIMARY KEY,
+    -- 先頭の '#' は付けずに保存する
+    name VARCHAR(64) NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS gongzuo_tags (
+    gongzuo_id INTEGER NOT NULL REFERENCES gongzuo(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (gongzuo_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS gongzuo_tags_tag_id_idx ON gongzuo_tags (tag_id);
+
+-- API で返す gongzuo。カテゴリー、プロジェクト、タグ (名前順) と休憩 (started_at 順の JSON) を含む
+-- ゴミ箱の gongzuo も含むので、必要なら deleted_at で絞り込む
+CREATE OR REPLACE VIEW gongzuo_details AS
+SELECT
+    gongzuo.id,
+    gongzuo.user_id,
+    gongzuo.content_id,
+    gongzuo.started_at,
+    gongzuo.ended_at,
+    categories.id AS category_id,
+    categories.name AS category_name,
+    categories.counts_as_work,
+    gongzuo.project_id,
+    projects.name AS project_name,
+    gongzuo.invoice_id,
+    gongzuo.deleted_at,
+    contents.content,
+    ARRAY(
+        SELECT
+            tags.name
+        FROM
+            gongzuo_tags
+        JOIN
+            tags
+        ON
+            gongzuo_tags.tag_id = tags.id
+        WHERE
+            gongzuo_tags.gongzuo_id = gongzuo.id
+        ORDER BY
+            tags.name
+    ) AS tags,
+    COALESCE(
+        (
+            SELECT
+                json_agg(
+                    json_build_object(
+                        'started_at', gongzuo_breaks.started_at,
+                        'ended_at', gongzuo_breaks.ended_at
+                    )
+                    ORDER BY gongzuo_breaks.started_at
+                )
+            FROM
+                gongzuo_breaks
+            WHERE
+                gongzuo_breaks.gongzuo_id = gongzuo.id
+        ),
+        '[]'
+    ) AS breaks
+FROM
+    gongzuo
+JOIN
+    contents
+ON
+    gongzuo.content_id = contents.id
+JOIN
+    categories
+ON
+    contents.category_id = categories.id
+LEFT JOIN
+    projects
+ON
+    gongzuo.project_id = projects.id;
+
+-- 請求書の明細。発行時点の値をコピーして持つので、元の gongzuo などが変わっても変わらない
+CREATE TABLE IF NOT EXISTS invoice_line_items (
+    id SERIAL PRIMARY KEY,
+    invoice_id INTEGER NOT NULL REFERENCES invoices(id),
+    gongzuo_id INTEGER NOT NULL REFERENCES gongzuo(id),
+    user_id INTEGER NOT NULL,
+    username VARCHAR(255) NOT NULL,
+    project_id INTEGER NOT NULL,
+    project_name VARCHAR(255) NOT NULL,
+    content VARCHAR(1023) NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    ended_at TIMESTAMP NOT NULL,
+    -- 実際の時間と、丸めた後の請求する時間
+    worked_seconds BIGINT NOT NULL,
+    billed_seconds BIGINT NOT NULL,
+    hourly_rate BIGINT NOT NULL,
+    amount BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS invoice_line_items_invoice_id_idx ON invoice_line_items (invoice_id);
@@ -0,0 +1,146 @@
+use crate::db::user::{UserFilter, UserHandlerTrait, UserRaw, Visibility};
+use crate::db::DB;
+use crate::error::Result;
+
+/// Users whose gongzuos a viewer can read.
+pub enum Readable {
+    All,
+    Users(Vec<i32>),
+}
+
+impl Readable {
+    pub fn contains(&self, user_id: i32) -> bool {
+        match self {
+            Readable::All => true,
+            Readable::Users(user_ids) => user_ids.contains(&user_id),
+        }
+    }
+
+    /// `None` means no restriction, which is how the db layer takes optional filters.
+    pub fn user_ids(&self) -> Option<Vec<i32>> {
+        match self {
+            Readable::All => None,
+            Readable::Users(user_ids) => Some(user_ids.clone()),
+        }
+    }
+}
+
+fn visible_to(
+    owner_id: i32,
+    visibility: Visibility,
+    viewer: &UserRaw,
+    teammate_ids: &[i32],
+) -> bool {
+    if viewer.is_admin || owner_id == viewer.id {
+        return true;
+    }
+    match visibility {
+        Visibility::Private => false,
+        Visibility::Team => teammate_ids.contains(&owner_id),
+        Visibility::Public => true,
+    }
+}
+
+pub async fn readable_users(db: &DB, viewer: &UserRaw) -> Result<Readable> {
+    if viewer.is_admin {
+        return Ok(Readable::All);
+    }
+
+    let teammate_ids = db.user_handler().teammate_ids(viewer.id).await?;
+    let user_ids = db
+        .user_handler()
+        .users(UserFilter {
+            include_admins: true,
+            status: None,
+        })
+        .await?
+        .iter()
+        .filter(|owner| visible_to(owner.id, owner.visibility(), viewer, &teammate_ids))
+        .map(|owner| owner.id)
+        .collect();
+
+    Ok(Readable::Users(user_ids))
+}
+
+/// Whether `viewer` can read the gongzuos of `owner_id`. Unknown users can't be read.
+pub async fn can_read(db: &DB, viewer: &UserRaw, owner_id: i32) -> Result<bool> {
+    if viewer.id == owner_id {
+        return Ok(true);
+    }
+
+    let Some(owner) = db.user_handler().get_user_by_id(owner_id).await? else {
+        return Ok(false);
+    };
+
+    let teammate_ids = match owner.visibility() {
+        Visibility::Team if !viewer.is_admin => db.user_handler().teammate_ids(viewer.id).await?,
+        _ => Vec::new(),
+    };
+
+    Ok(visible_to(
+        owner.id,
+        owner.visibility(),
+        viewer,
+        &teammate_ids,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    const OWNER: i32 = 1;
+    const TEAMMATE: i32 = 2;
+    const STRANGER: i32 = 3;
+
+    fn viewer(id: i32, is_admin: bool) -> UserRaw {
+        UserRaw {
+            id,
+            username: format!("user{id}"),
+            password: String::new(),
+            salt: String::new(),
+            created_at: NaiveDateTime::default(),
+            is_admin,
+            time_zone: "Asia/Tokyo".to_string(),
+            visibility: "team".to_string(),
+            deactivated_at: None,
+            password_reset_required: false,
+        }
+    }
+
+    #[test]
+    fn private_is_visible_only_to_the_owner() {
+        let teammate = viewer(TEAMMATE, false);
+        assert!(!visible_to(OWNER, Visibility::Private, &teammate, &[OWNER]));
+        assert!(visible_to(
+            OWNER,
+            Visibility::Private,
+            &viewer(OWNER, false),
+            &[]
+        ));
+    }
+
+    #[test]
+    fn team_is_visible_to_teammates_only() {
+        let teammate = viewer(TEAMMATE, false);
+        let stranger = viewer(STRANGER, false);
+        assert!(visible_to(OWNER, Visibility::Team, &teammate, &[OWNER]));
+        assert!(!visible_to(OWNER, Visibility::Team, &stranger, &[TEAMMATE]));
+    }
+
+    #[test]
+    fn public_is_visible_to_everyone() {
+        let stranger = viewer(STRANGER, false);
+        assert!(visible_to(OWNER, Visibility::Public, &stranger, &[]));
+    }
+
+    #[test]
+    fn admins_see_every_mode() {
+        let admin = viewer(STRANGER, true);
+        for visibility in [Visibility::Private, Visibility::Team, Visibility::Public] {
+            assert!(visible_to(OWNER, visibility, &admin, &[]));
+        }
+    }
+}
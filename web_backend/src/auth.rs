@@ -0,0 +1,409 @@
+use axum::{
+    extract::{FromRef, FromRequestParts, OriginalUri, Query},
+    http::{
+        header::{AUTHORIZATION, COOKIE, ORIGIN, SET_COOKIE},
+        request::Parts,
+        HeaderMap, HeaderValue, Method,
+    },
+    middleware::{from_extractor_with_state, FromExtractorLayer},
+};
+use serde::Deserialize;
+use serde_with::NoneAsEmptyString;
+
+use crate::{
+    db::{
+        access_token::{AccessTokenHandlerTrait, Scope},
+        role::{Permission, RoleHandlerTrait},
+        user::{UserHandlerTrait, UserRaw},
+        DB,
+    },
+    error::AppError,
+    password::{hash_token, ACCESS_TOKEN_PREFIX},
+    session::session_ttl,
+};
+
+pub const SESSION_COOKIE_NAME: &str = "session_token";
+
+/// Origins of the frontends, which CORS lets read responses.
+pub const ALLOWED_ORIGINS: [&str; 2] = ["http://localhost:3000", "https://gongzuo-one.vercel.app"];
+
+/// Header the frontends send with requests authenticated by the session cookie.
+/// Other sites can't send it without passing the CORS preflight.
+pub const CSRF_HEADER: &str = "x-requested-with";
+
+/// Legacy way of passing the session token.
+/// Kept for compatibility; prefer the `Authorization` header or the session cookie.
+#[serde_with::serde_as]
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SessionQuery {
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub session_token: Option<String>,
+}
+
+/// Looks up the session token in the `Authorization: Bearer` header,
+/// then the session cookie, then the legacy `session_token` query parameter.
+pub fn session_token_from_parts(parts: &Parts) -> Option<String> {
+    bearer_token(&parts.headers)
+        .or_else(|| cookie_token(&parts.headers))
+        .or_else(|| {
+            Query::<SessionQuery>::try_from_uri(&parts.uri)
+                .ok()
+                .and_then(|Query(query)| query.session_token)
+        })
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") || token.trim().is_empty() {
+        return None;
+    }
+    Some(token.trim().to_string())
+}
+
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, value)| *name == SESSION_COOKIE_NAME && !value.is_empty())
+        .map(|(_, value)| value.to_string())
+}
+
+/// `Set-Cookie` header which stores `session_token` as an HttpOnly cookie.
+pub fn session_cookie(session_token: &str) -> (axum::http::HeaderName, HeaderValue) {
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=None",
+        SESSION_COOKIE_NAME,
+        session_token,
+        session_ttl().num_seconds()
+    );
+    (SET_COOKIE, HeaderValue::from_str(&cookie).unwrap())
+}
+
+/// `Set-Cookie` header which removes the session cookie.
+pub fn clear_session_cookie() -> (axum::http::HeaderName, HeaderValue) {
+    let cookie = format!(
+        "{}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=None",
+        SESSION_COOKIE_NAME
+    );
+    (SET_COOKIE, HeaderValue::from_str(&cookie).unwrap())
+}
+
+/// Whether a request which the session cookie authenticates can change something.
+/// The cookie is sent cross-site, so unsafe methods need [`CSRF_HEADER`]
+/// or an `Origin` in [`ALLOWED_ORIGINS`].
+fn passes_csrf_check(method: &Method, headers: &HeaderMap) -> bool {
+    if method == Method::GET || method == Method::HEAD {
+        return true;
+    }
+    if headers.contains_key(CSRF_HEADER) {
+        return true;
+    }
+    headers
+        .get(ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .is_some_and(|origin| ALLOWED_ORIGINS.contains(&origin))
+}
+
+fn invalid_session_token() -> AppError {
+    AppError::Unauthorized(String::from("Invalid session token"))
+}
+
+/// Path of the request, including the prefixes of nested routers.
+fn request_path(parts: &Parts) -> &str {
+    // nest されたルーターでは `parts.uri` からプレフィックスが取り除かれている。
+    parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(parts.uri.path(), |OriginalUri(uri)| uri.path())
+}
+
+/// Routes a user whose password was reset by an admin can still reach,
+/// so they can set a new password or log out.
+fn allowed_while_password_reset_required(method: &Method, path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!(
+        (method, segments.as_slice()),
+        (&Method::PUT, ["me", "password"]) | (&Method::POST, ["logout"])
+    )
+}
+
+/// What a personal access token needs to reach a route.
+enum TokenRequirement {
+    /// Any token of the user, e.g. to find out whose token it is.
+    AnyScope,
+    Scope(Scope),
+}
+
+/// Personal access tokens reach only the routes listed here. Others get 403,
+/// so e.g. a token can't create tokens or touch sessions.
+fn token_requirement(method: &Method, path: &str) -> Option<TokenRequirement> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let read = method == Method::GET;
+
+    let scope = match segments.as_slice() {
+        ["me"] if read => return Some(TokenRequirement::AnyScope),
+        ["gongzuo", ..] if read => Scope::GongzuoRead,
+        ["gongzuo", ..] => Scope::GongzuoWrite,
+        ["users", _, "current"] | ["categories", ..] | ["tags"] if read => Scope::GongzuoRead,
+        ["reports", ..] | ["teams", _, "reports", ..] if read => Scope::ReportsRead,
+        _ => return None,
+    };
+    Some(TokenRequirement::Scope(scope))
+}
+
+/// Authenticates a personal access token and checks its scopes against the request.
+async fn authenticate_access_token(
+    db: &DB,
+    parts: &Parts,
+    access_token: &str,
+) -> Result<UserRaw, AppError> {
+    let invalid_access_token = || AppError::Unauthorized(String::from("Invalid access token"));
+
+    let (user_id, scopes) = db
+        .access_token_handler()
+        .ensure_access_token(&hash_token(access_token))
+        .await?
+        .ok_or_else(invalid_access_token)?;
+
+    // トークンは無効化のときに消えるが、念のため無効化されたユーザーも弾く。
+    let user = db
+        .user_handler()
+        .get_user_by_id(user_id)
+        .await?
+        .filter(|user| user.deactivated_at.is_none())
+        .ok_or_else(invalid_access_token)?;
+
+    match token_requirement(&parts.method, request_path(parts)) {
+        Some(TokenRequirement::AnyScope) => Ok(user),
+        Some(TokenRequirement::Scope(scope)) if scopes.contains(&scope) => Ok(user),
+        Some(TokenRequirement::Scope(scope)) => Err(AppError::Forbidden(format!(
+            "Scope {} is required",
+            scope.as_str()
+        ))),
+        None => Err(AppError::Forbidden(String::from(
+            "Access tokens can't access this resource",
+        ))),
+    }
+}
+
+/// The user who owns the session token of the request.
+/// Personal access tokens are accepted in place of session tokens
+/// on the routes their scopes cover. Users who must change their password
+/// are rejected with 403 except on `PUT /me/password` and `POST /logout`.
+/// Unsafe requests authenticated by the session cookie have to pass the CSRF check.
+pub struct AuthUser {
+    pub user: UserRaw,
+    /// The session token, or the personal access token.
+    pub session_token: String,
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    DB: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Some(session_token) = session_token_from_parts(parts) else {
+            return Err(invalid_session_token());
+        };
+
+        // Bearer ヘッダーがなければ cookie のトークンが使われている。
+        let from_cookie =
+            bearer_token(&parts.headers).is_none() && cookie_token(&parts.headers).is_some();
+        if from_cookie && !passes_csrf_check(&parts.method, &parts.headers) {
+            return Err(AppError::Forbidden(format!(
+                "Requests with the session cookie need the {} header",
+                CSRF_HEADER
+            )));
+        }
+
+        let db = DB::from_ref(state);
+        let user = if session_token.starts_with(ACCESS_TOKEN_PREFIX) {
+            authenticate_access_token(&db, parts, &session_token).await?
+        } else {
+            db.user_handler()
+                .ensure_session_token(&session_token)
+                .await?
+                .ok_or_else(invalid_session_token)?
+        };
+
+        if user.password_reset_required
+            && !allowed_while_password_reset_required(&parts.method, request_path(parts))
+        {
+            return Err(AppError::PasswordResetRequired(String::from(
+                "Change the password before using other resources",
+            )));
+        }
+
+        Ok(AuthUser {
+            user,
+            session_token,
+        })
+    }
+}
+
+/// Same as [`AuthUser`], but rejects non-admin users with 403.
+pub struct AdminUser {
+    pub user: UserRaw,
+    pub session_token: String,
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    DB: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthUser {
+            user,
+            session_token,
+        } = AuthUser::from_request_parts(parts, state).await?;
+
+        if !user.is_admin {
+            return Err(AppError::Forbidden(String::from(
+                "Only admin can access this resource",
+            )));
+        }
+
+        Ok(AdminUser {
+            user,
+            session_token,
+        })
+    }
+}
+
+/// Whether the user has the permission through a role. Admins have every permission.
+pub async fn has_permission(
+    db: &DB,
+    user: &UserRaw,
+    permission: Permission,
+) -> Result<bool, AppError> {
+    if user.is_admin {
+        return Ok(true);
+    }
+    db.role_handler().has_permission(user.id, permission).await
+}
+
+/// State of the [`require_permission`] layer.
+#[derive(Clone)]
+pub struct RequiredPermission {
+    db: DB,
+    permission: Permission,
+}
+
+impl FromRef<RequiredPermission> for DB {
+    fn from_ref(state: &RequiredPermission) -> Self {
+        state.db.clone()
+    }
+}
+
+/// Rejects users without the permission of the [`RequiredPermission`] state with 403.
+pub struct Permitted;
+
+#[axum::async_trait]
+impl FromRequestParts<RequiredPermission> for Permitted {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &RequiredPermission,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser { user, .. } = AuthUser::from_request_parts(parts, state).await?;
+
+        if !has_permission(&state.db, &user, state.permission).await? {
+            return Err(AppError::Forbidden(format!(
+                "Permission {} is required",
+                state.permission.as_str()
+            )));
+        }
+
+        Ok(Permitted)
+    }
+}
+
+/// Route layer which lets only users with the permission through, e.g.
+/// `post(handler).route_layer(require_permission(&db, Permission::UsersCreate))`.
+pub fn require_permission(
+    db: &DB,
+    permission: Permission,
+) -> FromExtractorLayer<Permitted, RequiredPermission> {
+    from_extractor_with_state(RequiredPermission {
+        db: db.clone(),
+        permission,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn safe_methods_pass_the_csrf_check() {
+        assert!(passes_csrf_check(&Method::GET, &headers(&[])));
+        assert!(passes_csrf_check(&Method::HEAD, &headers(&[])));
+    }
+
+    #[test]
+    fn unsafe_methods_need_the_csrf_header_or_an_allowed_origin() {
+        assert!(!passes_csrf_check(&Method::POST, &headers(&[])));
+        assert!(!passes_csrf_check(
+            &Method::POST,
+            &headers(&[("origin", "https://evil.example")])
+        ));
+        assert!(passes_csrf_check(
+            &Method::POST,
+            &headers(&[("origin", "https://gongzuo-one.vercel.app")])
+        ));
+        assert!(passes_csrf_check(
+            &Method::DELETE,
+            &headers(&[("x-requested-with", "XMLHttpRequest")])
+        ));
+    }
+
+    #[test]
+    fn password_change_and_logout_are_allowed_while_password_reset_required() {
+        assert!(allowed_while_password_reset_required(
+            &Method::PUT,
+            "/me/password"
+        ));
+        assert!(allowed_while_password_reset_required(
+            &Method::POST,
+            "/logout"
+        ));
+    }
+
+    #[test]
+    fn other_routes_are_rejected_while_password_reset_required() {
+        assert!(!allowed_while_password_reset_required(&Method::GET, "/me"));
+        assert!(!allowed_while_password_reset_required(
+            &Method::GET,
+            "/me/password"
+        ));
+        assert!(!allowed_while_password_reset_required(
+            &Method::GET,
+            "/gongzuo/gongzuos"
+        ));
+        assert!(!allowed_while_password_reset_required(
+            &Method::PUT,
+            "/me/password/extra"
+        ));
+    }
+}
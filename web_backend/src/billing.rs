@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+
+/// How worked time is rounded to a multiple of the rounding unit before it's billed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    Up,
+    Down,
+    /// Halves are rounded up.
+    #[default]
+    Nearest,
+}
+
+impl Rounding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rounding::Up => "up",
+            Rounding::Down => "down",
+            Rounding::Nearest => "nearest",
+        }
+    }
+}
+
+pub fn validate_rounding_minutes(rounding_minutes: i32) -> Result<()> {
+    if !(1..=24 * 60).contains(&rounding_minutes) {
+        return Err(AppError::Validation(String::from(
+            "rounding_minutes must be between 1 and 1440",
+        )));
+    }
+    Ok(())
+}
+
+/// Rounds `seconds` to a multiple of `rounding_minutes`.
+pub fn round_seconds(seconds: i64, rounding_minutes: i32, rounding: Rounding) -> i64 {
+    let unit = rounding_minutes as i64 * 60;
+    let units = match rounding {
+        Rounding::Up => (seconds + unit - 1) / unit,
+        Rounding::Down => seconds / unit,
+        Rounding::Nearest => (seconds + unit / 2) / unit,
+    };
+    units * unit
+}
+
+/// Amount in the minor unit of the currency, rounded half up.
+pub fn amount(billed_seconds: i64, hourly_rate: i64) -> i64 {
+    (billed_seconds * hourly_rate + 1800) / 3600
+}
@@ -1,9 +1,27 @@
+pub mod access_token;
+pub mod audit;
+pub mod category;
 pub mod gongzuo;
+pub mod invoice;
+pub mod login_failure;
+pub mod project;
+pub mod rate;
+pub mod revision;
+pub mod role;
+pub mod tag;
+pub mod team;
+pub mod totp;
 pub mod user;
 
 use sqlx::{Pool, Postgres};
 
-use self::{gongzuo::GongzuoHandlerTrait, user::UserHandlerTrait};
+use self::{
+    access_token::AccessTokenHandlerTrait, audit::AuditHandlerTrait,
+    category::CategoryHandlerTrait, gongzuo::GongzuoHandlerTrait, invoice::InvoiceHandlerTrait,
+    login_failure::LoginFailureHandlerTrait, project::ProjectHandlerTrait, rate::RateHandlerTrait,
+    revision::RevisionHandlerTrait, role::RoleHandlerTrait, tag::TagHandlerTrait,
+    team::TeamHandlerTrait, totp::TotpHandlerTrait, user::UserHandlerTrait,
+};
 
 #[derive(Clone)]
 pub struct DB {
@@ -15,6 +33,14 @@ impl DB {
         Self { pool }
     }
 
+    pub fn access_token_handler(&self) -> impl AccessTokenHandlerTrait + '_ {
+        access_token::AccessTokenHandler::new(&self.pool)
+    }
+
+    pub fn audit_handler(&self) -> impl AuditHandlerTrait + '_ {
+        audit::AuditHandler::new(&self.pool)
+    }
+
     pub fn user_handler(&self) -> impl UserHandlerTrait + '_ {
         user::UserHandler::new(&self.pool)
     }
@@ -22,4 +48,44 @@ impl DB {
     pub fn gongzuo_handler(&self) -> impl GongzuoHandlerTrait + '_ {
         gongzuo::GongzuoHandler::new(&self.pool)
     }
+
+    pub fn category_handler(&self) -> impl CategoryHandlerTrait + '_ {
+        category::CategoryHandler::new(&self.pool)
+    }
+
+    pub fn project_handler(&self) -> impl ProjectHandlerTrait + '_ {
+        project::ProjectHandler::new(&self.pool)
+    }
+
+    pub fn rate_handler(&self) -> impl RateHandlerTrait + '_ {
+        rate::RateHandler::new(&self.pool)
+    }
+
+    pub fn invoice_handler(&self) -> impl InvoiceHandlerTrait + '_ {
+        invoice::InvoiceHandler::new(&self.pool)
+    }
+
+    pub fn login_failure_handler(&self) -> impl LoginFailureHandlerTrait + '_ {
+        login_failure::LoginFailureHandler::new(&self.pool)
+    }
+
+    pub fn revision_handler(&self) -> impl RevisionHandlerTrait + '_ {
+        revision::RevisionHandler::new(&self.pool)
+    }
+
+    pub fn role_handler(&self) -> impl RoleHandlerTrait + '_ {
+        role::RoleHandler::new(&self.pool)
+    }
+
+    pub fn tag_handler(&self) -> impl TagHandlerTrait + '_ {
+        tag::TagHandler::new(&self.pool)
+    }
+
+    pub fn team_handler(&self) -> impl TeamHandlerTrait + '_ {
+        team::TeamHandler::new(&self.pool)
+    }
+
+    pub fn totp_handler(&self) -> impl TotpHandlerTrait + '_ {
+        totp::TotpHandler::new(&self.pool)
+    }
 }
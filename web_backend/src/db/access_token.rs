@@ -0,0 +1,260 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::Postgres;
+
+use crate::error::{AppError, Result};
+use crate::util::timezone::into_timezone;
+
+/// What a personal access token can do.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Read gongzuos and what they refer to, e.g. categories and tags.
+    #[serde(rename = "gongzuo:read")]
+    GongzuoRead,
+    /// Start, end, edit and delete gongzuos.
+    #[serde(rename = "gongzuo:write")]
+    GongzuoWrite,
+    /// Read reports, including the reports of teams.
+    #[serde(rename = "reports:read")]
+    ReportsRead,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::GongzuoRead => "gongzuo:read",
+            Scope::GongzuoWrite => "gongzuo:write",
+            Scope::ReportsRead => "reports:read",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "gongzuo:read" => Some(Scope::GongzuoRead),
+            "gongzuo:write" => Some(Scope::GongzuoWrite),
+            "reports:read" => Some(Scope::ReportsRead),
+            _ => None,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct AccessTokenRaw {
+    pub id: i32,
+    pub name: String,
+    /// Sorted by name.
+    pub scopes: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// A personal access token, without the token itself.
+#[derive(Serialize, Debug)]
+pub struct AccessToken {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub last_used_at: Option<DateTime<FixedOffset>>,
+}
+
+impl AccessToken {
+    pub fn from_raw(value: AccessTokenRaw, tz: Tz) -> Self {
+        let AccessTokenRaw {
+            id,
+            name,
+            scopes,
+            created_at,
+            last_used_at,
+        } = value;
+
+        AccessToken {
+            id,
+            name,
+            scopes,
+            created_at: into_timezone(created_at, tz),
+            last_used_at: last_used_at.map(|last_used_at| into_timezone(last_used_at, tz)),
+        }
+    }
+}
+
+pub struct AccessTokenHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> AccessTokenHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait AccessTokenHandlerTrait {
+    /// Tokens of the user, sorted by name.
+    async fn access_tokens(&self, user_id: i32) -> Result<Vec<AccessTokenRaw>>;
+    async fn create_access_token(
+        &self,
+        user_id: i32,
+        name: &str,
+        token_hash: &str,
+        scopes: &[Scope],
+    ) -> Result<AccessTokenRaw>;
+    /// Returns whether the user had the token.
+    async fn revoke_access_token(&self, user_id: i32, access_token_id: i32) -> Result<bool>;
+    /// Returns the ID of the owner and the scopes of the token, and updates `last_used_at`.
+    async fn ensure_access_token(&self, token_hash: &str) -> Result<Option<(i32, Vec<Scope>)>>;
+}
+
+#[axum::async_trait]
+impl AccessTokenHandlerTrait for AccessTokenHandler<'_> {
+    async fn access_tokens(&self, user_id: i32) -> Result<Vec<AccessTokenRaw>> {
+        let access_tokens = sqlx::query_as!(
+            AccessTokenRaw,
+            r#"
+            SELECT
+                access_tokens.id,
+                access_tokens.name,
+                ARRAY(
+                    SELECT scope FROM access_token_scopes
+                    WHERE access_token_scopes.access_token_id = access_tokens.id
+                    ORDER BY scope
+                ) AS "scopes!",
+                access_tokens.created_at,
+                access_tokens.last_used_at
+            FROM
+                access_tokens
+            WHERE
+                access_tokens.user_id = $1
+            ORDER BY
+                access_tokens.name
+            "#,
+            user_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(access_tokens)
+    }
+
+    async fn create_access_token(
+        &self,
+        user_id: i32,
+        name: &str,
+        token_hash: &str,
+        scopes: &[Scope],
+    ) -> Result<AccessTokenRaw> {
+        let scopes: Vec<&str> = scopes.iter().map(|scope| scope.as_str()).collect();
+
+        let mut transaction = self.pool.begin().await?;
+
+        let access_token_id = sqlx::query!(
+            r#"
+            INSERT INTO access_tokens (user_id, name, token_hash, created_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            user_id,
+            name,
+            token_hash,
+            Utc::now().naive_utc()
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| {
+            if let sqlx::Error::Database(database_error) = &e {
+                // unique_violation
+                if database_error.code().as_deref() == Some("23505") {
+                    return AppError::Conflict(format!("Access token {} already exists", name));
+                }
+            }
+            e.into()
+        })?
+        .id;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO access_token_scopes (access_token_id, scope)
+            SELECT $1, scope FROM UNNEST($2::VARCHAR[]) AS scope
+            ON CONFLICT DO NOTHING
+            "#,
+            access_token_id,
+            &scopes as &[&str]
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let access_token = sqlx::query_as!(
+            AccessTokenRaw,
+            r#"
+            SELECT
+                access_tokens.id,
+                access_tokens.name,
+                ARRAY(
+                    SELECT scope FROM access_token_scopes
+                    WHERE access_token_scopes.access_token_id = access_tokens.id
+                    ORDER BY scope
+                ) AS "scopes!",
+                access_tokens.created_at,
+                access_tokens.last_used_at
+            FROM
+                access_tokens
+            WHERE
+                access_tokens.id = $1
+            "#,
+            access_token_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(access_token)
+    }
+
+    async fn revoke_access_token(&self, user_id: i32, access_token_id: i32) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM access_tokens
+            WHERE id = $1 AND user_id = $2
+            "#,
+            access_token_id,
+            user_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn ensure_access_token(&self, token_hash: &str) -> Result<Option<(i32, Vec<Scope>)>> {
+        let Some(access_token) = sqlx::query!(
+            r#"
+            UPDATE access_tokens
+            SET last_used_at = $2
+            WHERE token_hash = $1
+            RETURNING
+                user_id,
+                ARRAY(
+                    SELECT scope FROM access_token_scopes
+                    WHERE access_token_scopes.access_token_id = access_tokens.id
+                ) AS "scopes!"
+            "#,
+            token_hash,
+            Utc::now().naive_utc()
+        )
+        .fetch_optional(self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let scopes = access_token
+            .scopes
+            .iter()
+            .filter_map(|scope| Scope::parse(scope))
+            .collect();
+
+        Ok(Some((access_token.user_id, scopes)))
+    }
+}
@@ -0,0 +1,200 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Postgres};
+
+use crate::error::Result;
+use crate::util::timezone::into_timezone;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    #[serde(rename = "user.create")]
+    UserCreate,
+    #[serde(rename = "user.deactivate")]
+    UserDeactivate,
+    #[serde(rename = "user.reactivate")]
+    UserReactivate,
+    #[serde(rename = "user.password_reset")]
+    UserPasswordReset,
+    #[serde(rename = "user.reset_token_issue")]
+    UserResetTokenIssue,
+    /// By the user, with the current password or a reset token.
+    #[serde(rename = "user.password_change")]
+    UserPasswordChange,
+    #[serde(rename = "user.rename")]
+    UserRename,
+    #[serde(rename = "user.admin_update")]
+    UserAdminUpdate,
+    #[serde(rename = "user.roles_update")]
+    UserRolesUpdate,
+    /// Forgetting the failed logins of the user.
+    #[serde(rename = "user.unlock")]
+    UserUnlock,
+    #[serde(rename = "user.totp_enable")]
+    UserTotpEnable,
+    /// By the user, or by an admin resetting it.
+    #[serde(rename = "user.totp_disable")]
+    UserTotpDisable,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::UserCreate => "user.create",
+            AuditAction::UserDeactivate => "user.deactivate",
+            AuditAction::UserReactivate => "user.reactivate",
+            AuditAction::UserPasswordReset => "user.password_reset",
+            AuditAction::UserResetTokenIssue => "user.reset_token_issue",
+            AuditAction::UserPasswordChange => "user.password_change",
+            AuditAction::UserRename => "user.rename",
+            AuditAction::UserAdminUpdate => "user.admin_update",
+            AuditAction::UserRolesUpdate => "user.roles_update",
+            AuditAction::UserUnlock => "user.unlock",
+            AuditAction::UserTotpEnable => "user.totp_enable",
+            AuditAction::UserTotpDisable => "user.totp_disable",
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct AuditLogRaw {
+    pub id: i32,
+    pub actor_id: Option<i32>,
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target_user_id: Option<i32>,
+    pub target_username: Option<String>,
+    pub details: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuditLog {
+    pub id: i32,
+    /// `None` once the user who did it is deleted.
+    pub actor_id: Option<i32>,
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target_user_id: Option<i32>,
+    pub target_username: Option<String>,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl AuditLog {
+    pub fn from_raw(value: AuditLogRaw, tz: Tz) -> Self {
+        let AuditLogRaw {
+            id,
+            actor_id,
+            actor_username,
+            action,
+            target_user_id,
+            target_username,
+            details,
+            created_at,
+        } = value;
+
+        AuditLog {
+            id,
+            actor_id,
+            actor_username,
+            action,
+            target_user_id,
+            target_username,
+            details,
+            created_at: into_timezone(created_at, tz),
+        }
+    }
+}
+
+pub struct AuditHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> AuditHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait AuditHandlerTrait {
+    /// Most recent first. If `target_user_id` is given, only the actions on the user.
+    async fn audit_logs(&self, target_user_id: Option<i32>, limit: i64)
+        -> Result<Vec<AuditLogRaw>>;
+}
+
+#[axum::async_trait]
+impl AuditHandlerTrait for AuditHandler<'_> {
+    async fn audit_logs(
+        &self,
+        target_user_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<AuditLogRaw>> {
+        let audit_logs = sqlx::query_as!(
+            AuditLogRaw,
+            r#"
+            SELECT
+                audit_logs.id,
+                audit_logs.actor_id,
+                actors.username AS "actor_username?",
+                audit_logs.action,
+                audit_logs.target_user_id,
+                targets.username AS "target_username?",
+                audit_logs.details,
+                audit_logs.created_at
+            FROM
+                audit_logs
+            LEFT JOIN
+                users AS actors
+            ON
+                audit_logs.actor_id = actors.id
+            LEFT JOIN
+                users AS targets
+            ON
+                audit_logs.target_user_id = targets.id
+            WHERE
+                $1::INTEGER IS NULL
+            OR
+                audit_logs.target_user_id = $1
+            ORDER BY
+                audit_logs.id DESC
+            LIMIT
+                $2
+            "#,
+            target_user_id,
+            limit
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(audit_logs)
+    }
+}
+
+/// Appends an audit log. Call this in the transaction which does the action.
+pub async fn record_audit(
+    conn: &mut PgConnection,
+    actor_id: i32,
+    action: AuditAction,
+    target_user_id: i32,
+    details: serde_json::Value,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            audit_logs (actor_id, action, target_user_id, details, created_at)
+        VALUES
+            ($1, $2, $3, $4, $5)
+        "#,
+        actor_id,
+        action.as_str(),
+        target_user_id,
+        details,
+        Utc::now().naive_utc()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use sqlx::Postgres;
+
+use crate::error::{AppError, Result};
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+    pub color: String,
+    /// Whether time spent in this category is counted as work in reports.
+    pub counts_as_work: bool,
+    /// Archived categories are kept for existing gongzuos but can't be used for new ones.
+    pub archived: bool,
+}
+
+pub struct CategoryPayload {
+    pub name: String,
+    pub color: String,
+    pub counts_as_work: bool,
+    pub archived: bool,
+}
+
+pub struct CategoryHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> CategoryHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait CategoryHandlerTrait {
+    async fn categories(&self, include_archived: bool) -> Result<Vec<Category>>;
+    async fn category_by_id(&self, category_id: i32) -> Result<Option<Category>>;
+    async fn create_category(&self, payload: CategoryPayload) -> Result<Category>;
+    async fn update_category(&self, category_id: i32, payload: CategoryPayload)
+        -> Result<Category>;
+    /// Only categories which no gongzuo uses can be deleted. Archive the others instead.
+    async fn delete_category(&self, category_id: i32) -> Result<()>;
+}
+
+#[axum::async_trait]
+impl CategoryHandlerTrait for CategoryHandler<'_> {
+    async fn categories(&self, include_archived: bool) -> Result<Vec<Category>> {
+        let categories = sqlx::query_as!(
+            Category,
+            r#"
+            SELECT * FROM categories
+            WHERE $1 OR archived = false
+            ORDER BY id
+            "#,
+            include_archived
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(categories)
+    }
+
+    async fn category_by_id(&self, category_id: i32) -> Result<Option<Category>> {
+        let category = sqlx::query_as!(
+            Category,
+            r#"
+            SELECT * FROM categories
+            WHERE id = $1
+            "#,
+            category_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(category)
+    }
+
+    async fn create_category(&self, payload: CategoryPayload) -> Result<Category> {
+        let CategoryPayload {
+            name,
+            color,
+            counts_as_work,
+            archived,
+        } = payload;
+
+        let category = sqlx::query_as!(
+            Category,
+            r#"
+            INSERT INTO categories (name, color, counts_as_work, archived)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *
+            "#,
+            name,
+            color,
+            counts_as_work,
+            archived
+        )
+        .fetch_one(self.pool)
+        .await
+        .map_err(|e| map_unique_violation(e, &name))?;
+
+        Ok(category)
+    }
+
+    async fn update_category(
+        &self,
+        category_id: i32,
+        payload: CategoryPayload,
+    ) -> Result<Category> {
+        let CategoryPayload {
+            name,
+            color,
+            counts_as_work,
+            archived,
+        } = payload;
+
+        let category = sqlx::query_as!(
+            Category,
+            r#"
+            UPDATE categories
+            SET name = $1, color = $2, counts_as_work = $3, archived = $4
+            WHERE id = $5
+            RETURNING *
+            "#,
+            name,
+            color,
+            counts_as_work,
+            archived,
+            category_id
+        )
+        .fetch_optional(self.pool)
+        .await
+        .map_err(|e| map_unique_violation(e, &name))?
+        .ok_or_else(|| AppError::NotFound(format!("Category {} not found", category_id)))?;
+
+        Ok(category)
+    }
+
+    async fn delete_category(&self, category_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let in_use = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM gongzuo
+                JOIN contents ON gongzuo.content_id = contents.id
+                WHERE contents.category_id = $1
+            ) AS "in_use!"
+            "#,
+            category_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?
+        .in_use;
+
+        if in_use {
+            return Err(AppError::Conflict(format!(
+                "Category {} is used by gongzuos. Archive it instead.",
+                category_id
+            )));
+        }
+
+        // どの gongzuo からも参照されていない contents は一緒に消してよい。
+        sqlx::query!(
+            r#"
+            DELETE FROM contents
+            WHERE category_id = $1
+            "#,
+            category_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM categories
+            WHERE id = $1
+            "#,
+            category_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Category {} not found",
+                category_id
+            )));
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+fn map_unique_violation(error: sqlx::Error, name: &str) -> AppError {
+    if let sqlx::Error::Database(database_error) = &error {
+        // unique_violation
+        if database_error.code().as_deref() == Some("23505") {
+            return AppError::Conflict(format!("Category {} already exists", name));
+        }
+    }
+    error.into()
+}
@@ -1,36 +1,33 @@
-use core::panic;
-
 use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use serde_repr::{Deserialize_repr, Serialize_repr};
-use sqlx::Postgres;
-
-use crate::util::timezone::into_jst;
-
-#[derive(
-    Debug, Serialize_repr, Deserialize_repr, sqlx::Type, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
-)]
-#[repr(i32)]
-pub enum ContentKind {
-    Work = 0,
-    NotWork = 1,
+use sqlx::types::Json;
+use sqlx::{PgConnection, Postgres};
+
+use crate::db::project::ensure_project_member;
+use crate::db::revision::{gongzuo_snapshot, record_revision, RevisionAction};
+use crate::db::tag::set_gongzuo_tags;
+use crate::error::{AppError, Result};
+use crate::util::timezone::into_timezone;
+
+/// A break inside a gongzuo. `ended_at` is `None` while the gongzuo is paused.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakRaw {
+    pub started_at: NaiveDateTime,
+    pub ended_at: Option<NaiveDateTime>,
 }
 
-impl From<i32> for ContentKind {
-    fn from(value: i32) -> Self {
-        match value {
-            0 => ContentKind::Work,
-            1 => ContentKind::NotWork,
-            _ => panic!("Invalid content kind"),
-        }
-    }
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Break {
+    pub started_at: DateTime<FixedOffset>,
+    pub ended_at: Option<DateTime<FixedOffset>>,
 }
 
-impl From<ContentKind> for i32 {
-    fn from(value: ContentKind) -> Self {
-        match value {
-            ContentKind::Work => 0,
-            ContentKind::NotWork => 1,
+impl Break {
+    pub fn from_raw(value: BreakRaw, tz: Tz) -> Self {
+        Break {
+            started_at: into_timezone(value.started_at, tz),
+            ended_at: value.ended_at.map(|ended_at| into_timezone(ended_at, tz)),
         }
     }
 }
@@ -42,8 +39,61 @@ pub struct GongzuoRaw {
     pub content_id: i32,
     pub started_at: NaiveDateTime,
     pub ended_at: Option<NaiveDateTime>,
-    pub content_kind: ContentKind,
+    pub category_id: i32,
+    pub category_name: String,
+    pub counts_as_work: bool,
+    pub project_id: Option<i32>,
+    pub project_name: Option<String>,
+    /// Invoiced gongzuos can't be edited or deleted.
+    pub invoice_id: Option<i32>,
+    /// Set while the gongzuo is in the trash.
+    pub deleted_at: Option<NaiveDateTime>,
     pub content: String,
+    pub tags: Vec<String>,
+    /// Sorted by `started_at`.
+    pub breaks: Json<Vec<BreakRaw>>,
+}
+
+impl GongzuoRaw {
+    pub fn is_paused(&self) -> bool {
+        self.breaks.iter().any(|b| b.ended_at.is_none())
+    }
+
+    /// Periods during which the gongzuo was actually worked on.
+    pub fn worked_periods(&self, now: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        worked_periods(self.started_at, self.ended_at, &self.breaks, now)
+    }
+
+    pub fn worked_seconds(&self, now: NaiveDateTime) -> i64 {
+        self.worked_periods(now)
+            .into_iter()
+            .map(|(started_at, ended_at)| (ended_at - started_at).num_seconds())
+            .sum()
+    }
+}
+
+/// `started_at..ended_at` minus the breaks. Ongoing gongzuos and breaks count up to `now`.
+pub fn worked_periods(
+    started_at: NaiveDateTime,
+    ended_at: Option<NaiveDateTime>,
+    breaks: &[BreakRaw],
+    now: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let ended_at = ended_at.unwrap_or(now);
+
+    let mut periods = Vec::new();
+    let mut period_start = started_at;
+    for b in breaks {
+        let break_end = b.ended_at.unwrap_or(now);
+        if b.started_at > period_start {
+            periods.push((period_start, b.started_at.min(ended_at)));
+        }
+        period_start = period_start.max(break_end);
+    }
+    periods.push((period_start, ended_at));
+
+    periods.retain(|(started_at, ended_at)| started_at < ended_at);
+    periods
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -53,24 +103,50 @@ pub struct Gongzuo {
     pub content_id: i32,
     pub started_at: DateTime<FixedOffset>,
     pub ended_at: Option<DateTime<FixedOffset>>,
-    pub content_kind: ContentKind,
+    pub category_id: i32,
+    pub category_name: String,
+    pub counts_as_work: bool,
+    pub project_id: Option<i32>,
+    pub project_name: Option<String>,
+    /// Invoiced gongzuos can't be edited or deleted.
+    pub invoice_id: Option<i32>,
+    pub deleted_at: Option<DateTime<FixedOffset>>,
     pub content: String,
+    pub tags: Vec<String>,
+    pub breaks: Vec<Break>,
+    pub paused: bool,
+    /// Time between `started_at` and `ended_at` (or now) minus the breaks.
+    pub worked_seconds: i64,
 }
 
-impl From<GongzuoRaw> for Gongzuo {
-    fn from(value: GongzuoRaw) -> Self {
+impl Gongzuo {
+    /// Timestamps are shown in `tz`, which is usually the viewer's time zone.
+    pub fn from_raw(value: GongzuoRaw, tz: Tz) -> Self {
+        let paused = value.is_paused();
+        let worked_seconds = value.worked_seconds(Utc::now().naive_utc());
+
         let GongzuoRaw {
             id,
             user_id,
             content_id,
             started_at,
             ended_at,
-            content_kind,
+            category_id,
+            category_name,
+            counts_as_work,
+            project_id,
+            project_name,
+            invoice_id,
+            deleted_at,
             content,
+            tags,
+            breaks: Json(breaks),
         } = value;
 
-        let started_at = into_jst(started_at);
-        let ended_at = ended_at.map(into_jst);
+        let started_at = into_timezone(started_at, tz);
+        let ended_at = ended_at.map(|ended_at| into_timezone(ended_at, tz));
+        let deleted_at = deleted_at.map(|deleted_at| into_timezone(deleted_at, tz));
+        let breaks = breaks.into_iter().map(|b| Break::from_raw(b, tz)).collect();
 
         Gongzuo {
             id,
@@ -78,8 +154,18 @@ impl From<GongzuoRaw> for Gongzuo {
             content_id,
             started_at,
             ended_at,
-            content_kind,
+            category_id,
+            category_name,
+            counts_as_work,
+            project_id,
+            project_name,
+            invoice_id,
+            deleted_at,
             content,
+            tags,
+            breaks,
+            paused,
+            worked_seconds,
         }
     }
 }
@@ -91,28 +177,123 @@ pub struct GongzuoHandler<'a> {
 pub struct GongzuoPayload {
     pub started_at: DateTime<Utc>,
     pub ended_at: Option<DateTime<Utc>>,
-    pub content_kind: ContentKind,
+    pub category_id: i32,
+    /// `None` leaves the project as it is, and `Some(None)` removes it.
+    /// A new gongzuo has no project with either. The user must be a member of the project.
+    pub project_id: Option<Option<i32>>,
     pub content: String,
+    /// `None` leaves the tags as they are. A new gongzuo has no tags then.
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Position in a listing sorted by `(started_at, id)`.
+/// Encoded as `<started_at in microseconds>_<id>` so that clients can treat it as opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GongzuoCursor {
+    pub started_at: NaiveDateTime,
+    pub id: i32,
+}
+
+impl GongzuoCursor {
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.started_at.timestamp_micros(), self.id)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let (started_at, id) = cursor.split_once('_')?;
+        let started_at = NaiveDateTime::from_timestamp_micros(started_at.parse().ok()?)?;
+        let id = id.parse().ok()?;
+        Some(Self { started_at, id })
+    }
+}
+
+impl From<&GongzuoRaw> for GongzuoCursor {
+    fn from(value: &GongzuoRaw) -> Self {
+        Self {
+            started_at: value.started_at,
+            id: value.id,
+        }
+    }
+}
+
+/// Conditions for listing gongzuos. `None` means "don't filter by this".
+#[derive(Debug, Clone, Default)]
+pub struct GongzuoFilter {
+    pub user_id: Option<i32>,
+    /// Only gongzuos of these users. Used to hide gongzuos the viewer can't read.
+    pub user_ids: Option<Vec<i32>>,
+    pub started_after: Option<DateTime<Utc>>,
+    pub started_before: Option<DateTime<Utc>>,
+    pub ended_after: Option<DateTime<Utc>>,
+    pub ended_before: Option<DateTime<Utc>>,
+    pub category_id: Option<i32>,
+    pub project_id: Option<i32>,
+    /// Case-insensitive substring of the content.
+    pub content: Option<String>,
+    /// Only gongzuos which have all of these tags.
+    pub tags: Option<Vec<String>>,
+    pub ongoing_only: bool,
+    pub order: SortOrder,
+    /// Only gongzuos after this position (in `order`) are returned.
+    pub cursor: Option<GongzuoCursor>,
+    /// `None` returns all of them.
+    pub limit: Option<i64>,
 }
 
 #[axum::async_trait]
 pub trait GongzuoHandlerTrait {
-    async fn all_gongzuos(&self) -> anyhow::Result<Vec<GongzuoRaw>>;
-    async fn gongzuos_by_user_id(&self, user_id: i32) -> anyhow::Result<Vec<GongzuoRaw>>;
-    async fn create_gongzuo(&self, user_id: i32, payload: GongzuoPayload) -> anyhow::Result<i32>;
-    async fn update_gongzuo(
+    /// Gongzuos of non-admin users matching `filter`, sorted by `(started_at, id)`.
+    async fn gongzuos(&self, filter: &GongzuoFilter) -> Result<Vec<GongzuoRaw>>;
+    async fn gongzuos_by_user_id(&self, user_id: i32) -> Result<Vec<GongzuoRaw>>;
+    /// Gongzuos of non-admin users which overlap `[from, to)`, including ongoing ones.
+    async fn gongzuos_overlapping(
         &self,
-        gongzuo_id: i32,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        user_id: Option<i32>,
+        tags: Option<&[String]>,
+        project_id: Option<i32>,
+    ) -> Result<Vec<GongzuoRaw>>;
+    async fn create_gongzuo(&self, user_id: i32, payload: GongzuoPayload) -> Result<i32>;
+    /// Ends the ongoing gongzuo at `payload.started_at` and starts a new one at the same instant,
+    /// in a single transaction. Returns the id of the new gongzuo.
+    async fn switch_gongzuo(
+        &self,
+        ongoing_gongzuo_id: i32,
         user_id: i32,
         payload: GongzuoPayload,
-    ) -> anyhow::Result<Result<(), String>>;
-    async fn delete_gongzuo(&self, id: i32, user_id: i32) -> anyhow::Result<Result<(), String>>;
-    async fn gongzuo_by_gongzuo_id(&self, gongzuo_id: i32) -> anyhow::Result<Option<GongzuoRaw>>;
-    async fn gongzuo_at(
+    ) -> Result<i32>;
+    /// `user_id` is the owner of the gongzuo, and `editor_id` the user who makes the change.
+    async fn update_gongzuo(
         &self,
+        gongzuo_id: i32,
         user_id: i32,
-        at: DateTime<Utc>,
-    ) -> anyhow::Result<Option<GongzuoRaw>>;
+        editor_id: i32,
+        payload: GongzuoPayload,
+    ) -> Result<()>;
+    /// Moves the gongzuo to the trash.
+    async fn delete_gongzuo(&self, id: i32, user_id: i32) -> Result<()>;
+    /// Gongzuos of the user in the trash, most recently deleted first.
+    async fn deleted_gongzuos(&self, user_id: i32) -> Result<Vec<GongzuoRaw>>;
+    /// Takes the gongzuo out of the trash unless another gongzuo was recorded during its period.
+    async fn restore_gongzuo(&self, gongzuo_id: i32, user_id: i32) -> Result<()>;
+    /// Deletes gongzuos in the trash for good if they were deleted before `deleted_before`.
+    /// Returns how many were deleted.
+    async fn purge_deleted_gongzuos(&self, deleted_before: NaiveDateTime) -> Result<u64>;
+    /// Starts a break in the ongoing gongzuo at `at`.
+    async fn pause_gongzuo(&self, gongzuo_id: i32, user_id: i32, at: DateTime<Utc>) -> Result<()>;
+    /// Ends the ongoing break of the gongzuo at `at`.
+    async fn resume_gongzuo(&self, gongzuo_id: i32, user_id: i32, at: DateTime<Utc>) -> Result<()>;
+    async fn gongzuo_by_gongzuo_id(&self, gongzuo_id: i32) -> Result<Option<GongzuoRaw>>;
+    async fn gongzuo_at(&self, user_id: i32, at: DateTime<Utc>) -> Result<Option<GongzuoRaw>>;
 }
 
 impl<'a> GongzuoHandler<'a> {
@@ -123,31 +304,95 @@ impl<'a> GongzuoHandler<'a> {
 
 #[axum::async_trait]
 impl GongzuoHandlerTrait for GongzuoHandler<'_> {
-    async fn all_gongzuos(&self) -> anyhow::Result<Vec<GongzuoRaw>> {
+    async fn gongzuos(&self, filter: &GongzuoFilter) -> Result<Vec<GongzuoRaw>> {
+        let ascending = filter.order == SortOrder::Asc;
+
         let gongzuos = sqlx::query_as!(
             GongzuoRaw,
             r#"
             SELECT
-                gongzuo.id AS id,
-                contents.id AS content_id,
-                user_id,
-                started_at,
-                ended_at,
-                content_kind,
-                content
+                gongzuo_details.id AS "id!",
+                gongzuo_details.user_id AS "user_id!",
+                gongzuo_details.content_id AS "content_id!",
+                gongzuo_details.started_at AS "started_at!",
+                gongzuo_details.ended_at,
+                gongzuo_details.category_id AS "category_id!",
+                gongzuo_details.category_name AS "category_name!",
+                gongzuo_details.counts_as_work AS "counts_as_work!",
+                gongzuo_details.project_id,
+                gongzuo_details.project_name,
+                gongzuo_details.invoice_id,
+                gongzuo_details.deleted_at,
+                gongzuo_details.content AS "content!",
+                gongzuo_details.tags AS "tags!",
+                gongzuo_details.breaks AS "breaks!: Json<Vec<BreakRaw>>"
             FROM
-                gongzuo
-            JOIN
-                contents
-            ON
-                gongzuo.content_id = contents.id
+                gongzuo_details
             JOIN
                 users
             ON
-                gongzuo.user_id = users.id
+                gongzuo_details.user_id = users.id
             WHERE
                 users.is_admin = false
-            "#
+            AND
+                gongzuo_details.deleted_at IS NULL
+            AND
+                ($1::INTEGER IS NULL OR gongzuo_details.user_id = $1)
+            AND
+                ($15::INTEGER[] IS NULL OR gongzuo_details.user_id = ANY($15))
+            AND
+                ($2::TIMESTAMP IS NULL OR started_at >= $2)
+            AND
+                ($3::TIMESTAMP IS NULL OR started_at < $3)
+            AND
+                ($4::TIMESTAMP IS NULL OR ended_at >= $4)
+            AND
+                ($5::TIMESTAMP IS NULL OR ended_at < $5)
+            AND
+                ($6::INTEGER IS NULL OR gongzuo_details.category_id = $6)
+            AND
+                ($14::INTEGER IS NULL OR gongzuo_details.project_id = $14)
+            AND
+                ($7::TEXT IS NULL OR strpos(lower(content), lower($7)) > 0)
+            AND
+                (NOT $8 OR ended_at IS NULL)
+            AND
+                (
+                    $13::TEXT[] IS NULL
+                OR
+                    $13 <@ gongzuo_details.tags::TEXT[]
+                )
+            AND
+                (
+                    $9::TIMESTAMP IS NULL
+                OR
+                    CASE WHEN $11
+                        THEN (started_at, gongzuo_details.id) > ($9, $10)
+                        ELSE (started_at, gongzuo_details.id) < ($9, $10)
+                    END
+                )
+            ORDER BY
+                CASE WHEN $11 THEN started_at END ASC,
+                CASE WHEN $11 THEN gongzuo_details.id END ASC,
+                started_at DESC,
+                gongzuo_details.id DESC
+            LIMIT $12
+            "#,
+            filter.user_id,
+            filter.started_after.map(|at| at.naive_utc()),
+            filter.started_before.map(|at| at.naive_utc()),
+            filter.ended_after.map(|at| at.naive_utc()),
+            filter.ended_before.map(|at| at.naive_utc()),
+            filter.category_id,
+            filter.content,
+            filter.ongoing_only,
+            filter.cursor.map(|cursor| cursor.started_at),
+            filter.cursor.map(|cursor| cursor.id),
+            ascending,
+            filter.limit,
+            filter.tags.as_deref(),
+            filter.project_id,
+            filter.user_ids.as_deref()
         )
         .fetch_all(self.pool)
         .await?;
@@ -155,28 +400,63 @@ impl GongzuoHandlerTrait for GongzuoHandler<'_> {
         Ok(gongzuos)
     }
 
-    async fn gongzuos_by_user_id(&self, user_id: i32) -> anyhow::Result<Vec<GongzuoRaw>> {
+    async fn gongzuos_overlapping(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        user_id: Option<i32>,
+        tags: Option<&[String]>,
+        project_id: Option<i32>,
+    ) -> Result<Vec<GongzuoRaw>> {
         let gongzuos = sqlx::query_as!(
             GongzuoRaw,
             r#"
             SELECT
-                gongzuo.id AS id,
-                contents.id AS content_id,
-                user_id,
-                started_at,
-                ended_at,
-                content_kind,
-                content
+                gongzuo_details.id AS "id!",
+                gongzuo_details.user_id AS "user_id!",
+                gongzuo_details.content_id AS "content_id!",
+                gongzuo_details.started_at AS "started_at!",
+                gongzuo_details.ended_at,
+                gongzuo_details.category_id AS "category_id!",
+                gongzuo_details.category_name AS "category_name!",
+                gongzuo_details.counts_as_work AS "counts_as_work!",
+                gongzuo_details.project_id,
+                gongzuo_details.project_name,
+                gongzuo_details.invoice_id,
+                gongzuo_details.deleted_at,
+                gongzuo_details.content AS "content!",
+                gongzuo_details.tags AS "tags!",
+                gongzuo_details.breaks AS "breaks!: Json<Vec<BreakRaw>>"
             FROM
-                gongzuo
+                gongzuo_details
             JOIN
-                contents
+                users
             ON
-                gongzuo.content_id = contents.id
+                gongzuo_details.user_id = users.id
             WHERE
-                gongzuo.user_id = $1
+                users.is_admin = false
+            AND
+                gongzuo_details.deleted_at IS NULL
+            AND
+                ($3::INTEGER IS NULL OR gongzuo_details.user_id = $3)
+            AND
+                tsrange(started_at, ended_at) && tsrange($1, $2)
+            AND
+                ($5::INTEGER IS NULL OR gongzuo_details.project_id = $5)
+            AND
+                (
+                    $4::TEXT[] IS NULL
+                OR
+                    $4 <@ gongzuo_details.tags::TEXT[]
+                )
+            ORDER BY
+                started_at
             "#,
-            user_id
+            from,
+            to,
+            user_id,
+            tags,
+            project_id
         )
         .fetch_all(self.pool)
         .await?;
@@ -184,99 +464,148 @@ impl GongzuoHandlerTrait for GongzuoHandler<'_> {
         Ok(gongzuos)
     }
 
-    async fn gongzuo_by_gongzuo_id(&self, gongzuo_id: i32) -> anyhow::Result<Option<GongzuoRaw>> {
-        let gongzuo = sqlx::query_as!(
+    async fn gongzuos_by_user_id(&self, user_id: i32) -> Result<Vec<GongzuoRaw>> {
+        let gongzuos = sqlx::query_as!(
             GongzuoRaw,
             r#"
             SELECT
-                gongzuo.id AS id,
-                contents.id AS content_id,
-                user_id,
-                started_at,
-                ended_at,
-                content_kind,
-                content
+                gongzuo_details.id AS "id!",
+                gongzuo_details.user_id AS "user_id!",
+                gongzuo_details.content_id AS "content_id!",
+                gongzuo_details.started_at AS "started_at!",
+                gongzuo_details.ended_at,
+                gongzuo_details.category_id AS "category_id!",
+                gongzuo_details.category_name AS "category_name!",
+                gongzuo_details.counts_as_work AS "counts_as_work!",
+                gongzuo_details.project_id,
+                gongzuo_details.project_name,
+                gongzuo_details.invoice_id,
+                gongzuo_details.deleted_at,
+                gongzuo_details.content AS "content!",
+                gongzuo_details.tags AS "tags!",
+                gongzuo_details.breaks AS "breaks!: Json<Vec<BreakRaw>>"
             FROM
-                gongzuo
-            JOIN
-                contents
-            ON
-                gongzuo.content_id = contents.id
+                gongzuo_details
             WHERE
-                gongzuo.id = $1
+                gongzuo_details.user_id = $1
+            AND
+                gongzuo_details.deleted_at IS NULL
             "#,
-            gongzuo_id
+            user_id
         )
-        .fetch_optional(self.pool)
+        .fetch_all(self.pool)
         .await?;
 
-        Ok(gongzuo)
+        Ok(gongzuos)
     }
 
-    async fn create_gongzuo(&self, user_id: i32, payload: GongzuoPayload) -> anyhow::Result<i32> {
-        let GongzuoPayload {
-            started_at,
-            ended_at,
-            content_kind,
-            content,
-        } = payload;
-
-        let contents = sqlx::query!(
+    async fn gongzuo_by_gongzuo_id(&self, gongzuo_id: i32) -> Result<Option<GongzuoRaw>> {
+        let gongzuo = sqlx::query_as!(
+            GongzuoRaw,
             r#"
             SELECT
-                id
+                gongzuo_details.id AS "id!",
+                gongzuo_details.user_id AS "user_id!",
+                gongzuo_details.content_id AS "content_id!",
+                gongzuo_details.started_at AS "started_at!",
+                gongzuo_details.ended_at,
+                gongzuo_details.category_id AS "category_id!",
+                gongzuo_details.category_name AS "category_name!",
+                gongzuo_details.counts_as_work AS "counts_as_work!",
+                gongzuo_details.project_id,
+                gongzuo_details.project_name,
+                gongzuo_details.invoice_id,
+                gongzuo_details.deleted_at,
+                gongzuo_details.content AS "content!",
+                gongzuo_details.tags AS "tags!",
+                gongzuo_details.breaks AS "breaks!: Json<Vec<BreakRaw>>"
             FROM
-                contents
+                gongzuo_details
             WHERE
-                content_kind = $1
+                gongzuo_details.id = $1
             AND
-                content = $2
+                gongzuo_details.deleted_at IS NULL
             "#,
-            i32::from(content_kind),
-            content
+            gongzuo_id
         )
-        .fetch_all(self.pool)
+        .fetch_optional(self.pool)
         .await?;
 
+        Ok(gongzuo)
+    }
+
+    async fn create_gongzuo(&self, user_id: i32, payload: GongzuoPayload) -> Result<i32> {
         let mut transaction = self.pool.begin().await?;
 
-        let content_id = if contents.is_empty() {
-            sqlx::query!(
-                r#"
-                INSERT INTO
-                    contents (content_kind, content)
-                VALUES
-                    ($1, $2)
-                RETURNING
-                    id
-                "#,
-                content_kind as i32,
-                content
-            )
-            .fetch_one(&mut *transaction)
-            .await?
-            .id
-        } else {
-            contents[0].id
-        };
+        let gongzuo_id = insert_gongzuo(&mut transaction, user_id, payload).await?;
+
+        transaction.commit().await?;
+
+        Ok(gongzuo_id)
+    }
+
+    async fn switch_gongzuo(
+        &self,
+        ongoing_gongzuo_id: i32,
+        user_id: i32,
+        payload: GongzuoPayload,
+    ) -> Result<i32> {
+        let switched_at = payload.started_at;
 
-        let gongzuo_id = sqlx::query!(
+        let mut transaction = self.pool.begin().await?;
+
+        let before = gongzuo_snapshot(&mut transaction, ongoing_gongzuo_id).await?;
+
+        let ended = sqlx::query!(
             r#"
-            INSERT INTO
-                gongzuo (user_id, content_id, started_at, ended_at)
-            VALUES
-                ($1, $2, $3, $4)
+            UPDATE
+                gongzuo
+            SET
+                ended_at = $1
+            WHERE
+                id = $2
+            AND
+                user_id = $3
+            AND
+                ended_at IS NULL
+            AND
+                deleted_at IS NULL
+            AND
+                started_at < $1
             RETURNING
                 id
             "#,
+            switched_at.naive_utc(),
+            ongoing_gongzuo_id,
+            user_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(map_constraint_error)?;
+
+        if ended.is_none() {
+            transaction.rollback().await?;
+            return Err(AppError::Conflict(format!(
+                "Gongzuo {} is not ongoing",
+                ongoing_gongzuo_id
+            )));
+        }
+
+        // 休憩中なら、休憩も切り替えた時点で終わる。
+        close_breaks(&mut transaction, ongoing_gongzuo_id, switched_at).await?;
+
+        let after = gongzuo_snapshot(&mut transaction, ongoing_gongzuo_id).await?;
+        record_revision(
+            &mut transaction,
+            ongoing_gongzuo_id,
             user_id,
-            content_id,
-            started_at.naive_utc(),
-            ended_at.map(|ended_at| ended_at.naive_utc())
+            RevisionAction::Update,
+            before,
+            after,
         )
-        .fetch_one(&mut *transaction)
-        .await?
-        .id;
+        .await?;
+
+        let gongzuo_id = insert_gongzuo(&mut transaction, user_id, payload).await?;
 
         transaction.commit().await?;
 
@@ -287,181 +616,495 @@ impl GongzuoHandlerTrait for GongzuoHandler<'_> {
         &self,
         gongzuo_id: i32,
         user_id: i32,
+        editor_id: i32,
         payload: GongzuoPayload,
-    ) -> anyhow::Result<Result<(), String>> {
+    ) -> Result<()> {
         let GongzuoPayload {
             started_at,
             ended_at,
-            content_kind,
+            category_id,
+            project_id,
             content,
+            tags,
         } = payload;
 
-        let gongzuo_between = sqlx::query!(
+        validate_period(started_at, ended_at)?;
+
+        let mut transaction = self.pool.begin().await?;
+
+        // 変更する前に行をロックして持ち主を確かめる。
+        let current = sqlx::query!(
             r#"
             SELECT
-                id
+                user_id,
+                project_id
             FROM
                 gongzuo
             WHERE
-                id != $1
-            AND
-                user_id = $2
+                id = $1
             AND
-                started_at <= $3
+                deleted_at IS NULL
+            FOR UPDATE
+            "#,
+            gongzuo_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Gongzuo {} not found", gongzuo_id)))?;
+
+        if current.user_id != user_id {
+            return Err(AppError::Forbidden(String::from("User id mismatch")));
+        }
+
+        ensure_not_invoiced(&mut transaction, gongzuo_id).await?;
+
+        ensure_no_overlap(
+            &mut transaction,
+            user_id,
+            started_at,
+            ended_at,
+            Some(gongzuo_id),
+        )
+        .await?;
+
+        let before = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+
+        let content_id = find_or_create_content(&mut transaction, category_id, &content).await?;
+
+        let current_project_id = current.project_id;
+        let project_id = project_id.unwrap_or(current_project_id);
+
+        // プロジェクトを変えないなら、メンバーから外れた後でも編集できる。
+        if let Some(project_id) = project_id {
+            if current_project_id != Some(project_id) {
+                ensure_project_member(&mut transaction, project_id, user_id).await?;
+            }
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE
+                gongzuo
+            SET
+                content_id = $1,
+                project_id = $2,
+                started_at = $3,
+                ended_at = $4
+            WHERE
+                id = $5
+            "#,
+            content_id,
+            project_id,
+            started_at.naive_utc(),
+            ended_at.map(|ended_at| ended_at.naive_utc()),
+            gongzuo_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(map_constraint_error)?;
+
+        if let Some(tags) = tags {
+            set_gongzuo_tags(&mut transaction, gongzuo_id, &tags).await?;
+        }
+
+        clip_breaks(&mut transaction, gongzuo_id, started_at, ended_at).await?;
+
+        let after = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+        record_revision(
+            &mut transaction,
+            gongzuo_id,
+            editor_id,
+            RevisionAction::Update,
+            before,
+            after,
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_gongzuo(&self, gongzuo_id: i32, user_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        // 他のユーザーの gongzuo については、あるかどうか以外は何も返さない。
+        let owner_id = sqlx::query!(
+            r#"
+            SELECT
+                user_id
+            FROM
+                gongzuo
+            WHERE
+                id = $1
             AND
-                (ended_at IS NULL OR ended_at > $3)
+                deleted_at IS NULL
+            FOR UPDATE
+            "#,
+            gongzuo_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Gongzuo {} not found", gongzuo_id)))?
+        .user_id;
+
+        if owner_id != user_id {
+            return Err(AppError::Forbidden(String::from("User id mismatch")));
+        }
+
+        ensure_not_invoiced(&mut transaction, gongzuo_id).await?;
+
+        let before = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+
+        // ゴミ箱に移すだけ。保持期間が過ぎたら purge_deleted_gongzuos で消える。
+        sqlx::query!(
+            r#"
+            UPDATE
+                gongzuo
+            SET
+                deleted_at = NOW() AT TIME ZONE 'UTC'
+            WHERE
+                id = $1
             "#,
             gongzuo_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        record_revision(
+            &mut transaction,
+            gongzuo_id,
             user_id,
-            started_at.naive_utc()
+            RevisionAction::Delete,
+            before,
+            None,
         )
-        .fetch_optional(self.pool)
         .await?;
 
-        if gongzuo_between.is_some() {
-            return Ok(Err(String::from(
-                "Gongzuo already exists during the period.",
-            )));
-        }
+        transaction.commit().await?;
 
-        let content_ids = sqlx::query!(
+        Ok(())
+    }
+
+    async fn deleted_gongzuos(&self, user_id: i32) -> Result<Vec<GongzuoRaw>> {
+        let gongzuos = sqlx::query_as!(
+            GongzuoRaw,
             r#"
             SELECT
-                id
+                gongzuo_details.id AS "id!",
+                gongzuo_details.user_id AS "user_id!",
+                gongzuo_details.content_id AS "content_id!",
+                gongzuo_details.started_at AS "started_at!",
+                gongzuo_details.ended_at,
+                gongzuo_details.category_id AS "category_id!",
+                gongzuo_details.category_name AS "category_name!",
+                gongzuo_details.counts_as_work AS "counts_as_work!",
+                gongzuo_details.project_id,
+                gongzuo_details.project_name,
+                gongzuo_details.invoice_id,
+                gongzuo_details.deleted_at,
+                gongzuo_details.content AS "content!",
+                gongzuo_details.tags AS "tags!",
+                gongzuo_details.breaks AS "breaks!: Json<Vec<BreakRaw>>"
             FROM
-                contents
+                gongzuo_details
             WHERE
-                content_kind = $1
+                gongzuo_details.user_id = $1
             AND
-                content = $2
+                gongzuo_details.deleted_at IS NOT NULL
+            ORDER BY
+                gongzuo_details.deleted_at DESC
             "#,
-            i32::from(content_kind),
-            content
+            user_id
         )
         .fetch_all(self.pool)
         .await?;
 
+        Ok(gongzuos)
+    }
+
+    async fn restore_gongzuo(&self, gongzuo_id: i32, user_id: i32) -> Result<()> {
         let mut transaction = self.pool.begin().await?;
 
-        let content_id = if content_ids.is_empty() {
-            sqlx::query!(
-                r#"
-                INSERT INTO
-                    contents (content_kind, content)
-                VALUES
-                    ($1, $2)
-                RETURNING
-                    id
-                "#,
-                content_kind as i32,
-                content
-            )
-            .fetch_one(&mut *transaction)
-            .await?
-            .id
-        } else {
-            content_ids[0].id
-        };
+        let gongzuo = sqlx::query!(
+            r#"
+            SELECT
+                user_id,
+                started_at,
+                ended_at
+            FROM
+                gongzuo
+            WHERE
+                id = $1
+            AND
+                deleted_at IS NOT NULL
+            FOR UPDATE
+            "#,
+            gongzuo_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound(format!("Gongzuo {} not found in the trash", gongzuo_id))
+        })?;
+
+        if gongzuo.user_id != user_id {
+            return Err(AppError::Forbidden(String::from("User id mismatch")));
+        }
+
+        // ゴミ箱にある間に同じ期間に別の gongzuo が記録されているかもしれない。
+        ensure_no_overlap(
+            &mut transaction,
+            user_id,
+            DateTime::from_naive_utc_and_offset(gongzuo.started_at, Utc),
+            gongzuo
+                .ended_at
+                .map(|ended_at| DateTime::from_naive_utc_and_offset(ended_at, Utc)),
+            Some(gongzuo_id),
+        )
+        .await?;
 
-        // user_id が一致しなければrollbackする。
-        let Some(fetched_user_id) = sqlx::query!(
+        sqlx::query!(
             r#"
             UPDATE
                 gongzuo
             SET
-                content_id = $1,
-                started_at = $2,
-                ended_at = $3
+                deleted_at = NULL
             WHERE
-                id = $4
-            RETURNING
-                user_id
+                id = $1
+            "#,
+            gongzuo_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(map_constraint_error)?;
+
+        let after = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+        record_revision(
+            &mut transaction,
+            gongzuo_id,
+            user_id,
+            RevisionAction::Restore,
+            None,
+            after,
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn purge_deleted_gongzuos(&self, deleted_before: NaiveDateTime) -> Result<u64> {
+        let purged = sqlx::query!(
+            r#"
+            DELETE
+            FROM
+                gongzuo
+            WHERE
+                deleted_at < $1
+            "#,
+            deleted_before
+        )
+        .execute(self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(purged)
+    }
+
+    async fn pause_gongzuo(&self, gongzuo_id: i32, user_id: i32, at: DateTime<Utc>) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let gongzuo = sqlx::query!(
+            r#"
+            SELECT
+                user_id,
+                started_at,
+                ended_at,
+                EXISTS (
+                    SELECT 1 FROM gongzuo_breaks
+                    WHERE gongzuo_id = gongzuo.id AND ended_at IS NULL
+                ) AS "paused!"
+            FROM
+                gongzuo
+            WHERE
+                id = $1
+            AND
+                deleted_at IS NULL
+            FOR UPDATE
             "#,
-            content_id,
-            started_at.naive_utc(),
-            ended_at.map(|ended_at| ended_at.naive_utc()),
             gongzuo_id
         )
         .fetch_optional(&mut *transaction)
         .await?
-        .map(|r| r.user_id) else {
-            transaction.rollback().await?;
-            return Ok(Err(format!("Gongzuo {} not found", gongzuo_id)));
-        };
+        .ok_or_else(|| AppError::NotFound(format!("Gongzuo {} not found", gongzuo_id)))?;
 
-        if fetched_user_id != user_id {
-            transaction.rollback().await?;
-            return Ok(Err(String::from("User id mismatch")));
+        if gongzuo.user_id != user_id {
+            return Err(AppError::Forbidden(String::from("User id mismatch")));
         }
+        if gongzuo.ended_at.is_some() {
+            return Err(AppError::Conflict(format!(
+                "Gongzuo {} already ended",
+                gongzuo_id
+            )));
+        }
+        if gongzuo.paused {
+            return Err(AppError::Conflict(format!(
+                "Gongzuo {} is already paused",
+                gongzuo_id
+            )));
+        }
+        if at.naive_utc() <= gongzuo.started_at {
+            return Err(AppError::Validation(String::from(
+                "A break must start after the gongzuo started",
+            )));
+        }
+
+        let before = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                gongzuo_breaks (gongzuo_id, started_at)
+            VALUES
+                ($1, $2)
+            "#,
+            gongzuo_id,
+            at.naive_utc()
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(map_constraint_error)?;
+
+        let after = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+        record_revision(
+            &mut transaction,
+            gongzuo_id,
+            user_id,
+            RevisionAction::Pause,
+            before,
+            after,
+        )
+        .await?;
 
         transaction.commit().await?;
 
-        Ok(Ok(()))
+        Ok(())
     }
 
-    async fn delete_gongzuo(
-        &self,
-        gongzuo_id: i32,
-        user_id: i32,
-    ) -> anyhow::Result<Result<(), String>> {
-        println!(
-            "delete_gongzuo: gongzuo_id: {}, user_id: {}",
-            gongzuo_id, user_id
-        );
+    async fn resume_gongzuo(&self, gongzuo_id: i32, user_id: i32, at: DateTime<Utc>) -> Result<()> {
         let mut transaction = self.pool.begin().await?;
 
-        // user_id が一致しなければrollbackする。
-        let Some(fetched_user_id) = sqlx::query!(
+        let fetched_user_id = sqlx::query!(
             r#"
-            DELETE
+            SELECT
+                user_id
             FROM
                 gongzuo
             WHERE
                 id = $1
-            RETURNING
-                user_id
+            AND
+                deleted_at IS NULL
+            FOR UPDATE
             "#,
-            gongzuo_id,
+            gongzuo_id
         )
         .fetch_optional(&mut *transaction)
         .await?
-        .map(|r| r.user_id) else {
-            transaction.rollback().await?;
-            return Ok(Err(format!("Gongzuo {} not found", gongzuo_id)));
-        };
+        .ok_or_else(|| AppError::NotFound(format!("Gongzuo {} not found", gongzuo_id)))?
+        .user_id;
 
         if fetched_user_id != user_id {
-            transaction.rollback().await?;
-            return Ok(Err("User id mismatch".to_string()));
+            return Err(AppError::Forbidden(String::from("User id mismatch")));
+        }
+
+        let Some(ongoing_break) = sqlx::query!(
+            r#"
+            SELECT
+                id,
+                started_at
+            FROM
+                gongzuo_breaks
+            WHERE
+                gongzuo_id = $1
+            AND
+                ended_at IS NULL
+            FOR UPDATE
+            "#,
+            gongzuo_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        else {
+            return Err(AppError::Conflict(format!(
+                "Gongzuo {} is not paused",
+                gongzuo_id
+            )));
+        };
+
+        if at.naive_utc() <= ongoing_break.started_at {
+            return Err(AppError::Validation(String::from(
+                "The resume time must be after the pause started",
+            )));
         }
 
+        let before = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE
+                gongzuo_breaks
+            SET
+                ended_at = $2
+            WHERE
+                id = $1
+            "#,
+            ongoing_break.id,
+            at.naive_utc()
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let after = gongzuo_snapshot(&mut transaction, gongzuo_id).await?;
+        record_revision(
+            &mut transaction,
+            gongzuo_id,
+            user_id,
+            RevisionAction::Resume,
+            before,
+            after,
+        )
+        .await?;
+
         transaction.commit().await?;
 
-        Ok(Ok(()))
+        Ok(())
     }
 
-    async fn gongzuo_at(
-        &self,
-        user_id: i32,
-        at: DateTime<Utc>,
-    ) -> anyhow::Result<Option<GongzuoRaw>> {
+    async fn gongzuo_at(&self, user_id: i32, at: DateTime<Utc>) -> Result<Option<GongzuoRaw>> {
         let row = sqlx::query_as!(
             GongzuoRaw,
             r#"
             SELECT
-                gongzuo.id AS id,
-                contents.id AS content_id,
-                user_id,
-                started_at,
-                ended_at,
-                content_kind,
-                content
-            FROM gongzuo
-            JOIN
-                contents
-            ON
-                gongzuo.content_id = contents.id
-            WHERE user_id = $1 AND started_at <= $2 AND (ended_at IS NULL OR ended_at > $2)
+                gongzuo_details.id AS "id!",
+                gongzuo_details.user_id AS "user_id!",
+                gongzuo_details.content_id AS "content_id!",
+                gongzuo_details.started_at AS "started_at!",
+                gongzuo_details.ended_at,
+                gongzuo_details.category_id AS "category_id!",
+                gongzuo_details.category_name AS "category_name!",
+                gongzuo_details.counts_as_work AS "counts_as_work!",
+                gongzuo_details.project_id,
+                gongzuo_details.project_name,
+                gongzuo_details.invoice_id,
+                gongzuo_details.deleted_at,
+                gongzuo_details.content AS "content!",
+                gongzuo_details.tags AS "tags!",
+                gongzuo_details.breaks AS "breaks!: Json<Vec<BreakRaw>>"
+            FROM
+                gongzuo_details
+            WHERE user_id = $1 AND deleted_at IS NULL AND started_at <= $2 AND (ended_at IS NULL OR ended_at > $2)
             "#,
             user_id,
             at.naive_utc(),
@@ -472,3 +1115,320 @@ impl GongzuoHandlerTrait for GongzuoHandler<'_> {
         Ok(row)
     }
 }
+
+async fn insert_gongzuo(
+    conn: &mut PgConnection,
+    user_id: i32,
+    payload: GongzuoPayload,
+) -> Result<i32> {
+    let GongzuoPayload {
+        started_at,
+        ended_at,
+        category_id,
+        project_id,
+        content,
+        tags,
+    } = payload;
+
+    validate_period(started_at, ended_at)?;
+
+    ensure_no_overlap(&mut *conn, user_id, started_at, ended_at, None).await?;
+
+    let content_id = find_or_create_content(&mut *conn, category_id, &content).await?;
+
+    let project_id = project_id.flatten();
+    if let Some(project_id) = project_id {
+        ensure_project_member(&mut *conn, project_id, user_id).await?;
+    }
+
+    let gongzuo_id = sqlx::query!(
+        r#"
+        INSERT INTO
+            gongzuo (user_id, content_id, project_id, started_at, ended_at)
+        VALUES
+            ($1, $2, $3, $4, $5)
+        RETURNING
+            id
+        "#,
+        user_id,
+        content_id,
+        project_id,
+        started_at.naive_utc(),
+        ended_at.map(|ended_at| ended_at.naive_utc())
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(map_constraint_error)?
+    .id;
+
+    if let Some(tags) = tags {
+        set_gongzuo_tags(&mut *conn, gongzuo_id, &tags).await?;
+    }
+
+    let after = gongzuo_snapshot(&mut *conn, gongzuo_id).await?;
+    record_revision(
+        &mut *conn,
+        gongzuo_id,
+        user_id,
+        RevisionAction::Create,
+        None,
+        after,
+    )
+    .await?;
+
+    Ok(gongzuo_id)
+}
+
+/// `ended_at` must come after `started_at`. An open-ended gongzuo is always valid.
+fn validate_period(started_at: DateTime<Utc>, ended_at: Option<DateTime<Utc>>) -> Result<()> {
+    match ended_at {
+        Some(ended_at) if ended_at <= started_at => Err(AppError::Validation(String::from(
+            "ended_at must be after started_at",
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects the period `[started_at, ended_at)` if it intersects any other gongzuo of the user.
+/// `ended_at = None` means the period is still ongoing, i.e. it extends forever.
+async fn ensure_no_overlap(
+    conn: &mut PgConnection,
+    user_id: i32,
+    started_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+    except_gongzuo_id: Option<i32>,
+) -> Result<()> {
+    let overlapping = sqlx::query!(
+        r#"
+        SELECT
+            id
+        FROM
+            gongzuo
+        WHERE
+            user_id = $1
+        AND
+            deleted_at IS NULL
+        AND
+            ($4::INTEGER IS NULL OR id != $4)
+        AND
+            tsrange(started_at, ended_at) && tsrange($2, $3)
+        ORDER BY
+            started_at
+        LIMIT 1
+        "#,
+        user_id,
+        started_at.naive_utc(),
+        ended_at.map(|ended_at| ended_at.naive_utc()),
+        except_gongzuo_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some(overlapping) = overlapping {
+        return Err(AppError::Overlap(format!(
+            "Gongzuo {} already exists during the period.",
+            overlapping.id
+        )));
+    }
+
+    Ok(())
+}
+
+/// Ends the ongoing break of the gongzuo at `at`, if any.
+async fn close_breaks(conn: &mut PgConnection, gongzuo_id: i32, at: DateTime<Utc>) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE
+            gongzuo_breaks
+        SET
+            ended_at = $2
+        WHERE
+            gongzuo_id = $1
+        AND
+            ended_at IS NULL
+        "#,
+        gongzuo_id,
+        at.naive_utc()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Keeps breaks within `[started_at, ended_at)` after the period of the gongzuo changed.
+/// Breaks entirely outside the period are removed, and the others are cut at its ends.
+async fn clip_breaks(
+    conn: &mut PgConnection,
+    gongzuo_id: i32,
+    started_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let started_at = started_at.naive_utc();
+    let ended_at = ended_at.map(|ended_at| ended_at.naive_utc());
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            gongzuo_breaks
+        WHERE
+            gongzuo_id = $1
+        AND
+            (
+                ($3::TIMESTAMP IS NOT NULL AND started_at >= $3)
+            OR
+                ended_at <= $2
+            )
+        "#,
+        gongzuo_id,
+        started_at,
+        ended_at
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            gongzuo_breaks
+        SET
+            started_at = GREATEST(started_at, $2),
+            ended_at = CASE
+                WHEN $3::TIMESTAMP IS NULL THEN ended_at
+                ELSE LEAST(COALESCE(ended_at, $3), $3)
+            END
+        WHERE
+            gongzuo_id = $1
+        "#,
+        gongzuo_id,
+        started_at,
+        ended_at
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Invoices keep snapshots of their gongzuos, so invoiced gongzuos must not change.
+/// The row is locked so that it can't be invoiced until the transaction ends.
+async fn ensure_not_invoiced(conn: &mut PgConnection, gongzuo_id: i32) -> Result<()> {
+    let invoice_id = sqlx::query!(
+        r#"
+        SELECT
+            invoice_id
+        FROM
+            gongzuo
+        WHERE
+            id = $1
+        FOR UPDATE
+        "#,
+        gongzuo_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .and_then(|r| r.invoice_id);
+
+    if let Some(invoice_id) = invoice_id {
+        return Err(AppError::Conflict(format!(
+            "Gongzuo {} is already invoiced in invoice {}",
+            gongzuo_id, invoice_id
+        )));
+    }
+
+    Ok(())
+}
+
+async fn find_or_create_content(
+    conn: &mut PgConnection,
+    category_id: i32,
+    content: &str,
+) -> Result<i32> {
+    let category = sqlx::query!(
+        r#"
+        SELECT
+            archived
+        FROM
+            categories
+        WHERE
+            id = $1
+        "#,
+        category_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match category {
+        None => {
+            return Err(AppError::Validation(format!(
+                "Category {} not found",
+                category_id
+            )))
+        }
+        Some(category) if category.archived => {
+            return Err(AppError::Validation(format!(
+                "Category {} is archived",
+                category_id
+            )))
+        }
+        Some(_) => {}
+    }
+
+    let content_id = sqlx::query!(
+        r#"
+        SELECT
+            id
+        FROM
+            contents
+        WHERE
+            category_id = $1
+        AND
+            content = $2
+        "#,
+        category_id,
+        content
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some(content_id) = content_id {
+        return Ok(content_id.id);
+    }
+
+    let content_id = sqlx::query!(
+        r#"
+        INSERT INTO
+            contents (category_id, content)
+        VALUES
+            ($1, $2)
+        RETURNING
+            id
+        "#,
+        category_id,
+        content
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+
+    Ok(content_id)
+}
+
+/// The checks in this module run before writing, but the constraints on `gongzuo`
+/// still catch concurrent writes. Turn their violations into client errors.
+fn map_constraint_error(error: sqlx::Error) -> AppError {
+    if let sqlx::Error::Database(database_error) = &error {
+        match database_error.code().as_deref() {
+            // exclusion_violation
+            Some("23P01") => {
+                return AppError::Overlap(String::from("Gongzuo already exists during the period."))
+            }
+            // check_violation
+            Some("23514") => {
+                return AppError::Validation(String::from("ended_at must be after started_at"))
+            }
+            _ => {}
+        }
+    }
+    error.into()
+}
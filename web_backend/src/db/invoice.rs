@@ -0,0 +1,587 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use serde::Serialize;
+use sqlx::types::Json;
+use sqlx::{PgConnection, Postgres};
+
+use crate::billing::{amount, round_seconds, Rounding};
+use crate::db::gongzuo::{worked_periods, BreakRaw};
+use crate::error::{AppError, Result};
+use crate::report::start_of_day;
+use crate::util::timezone::into_timezone;
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct InvoiceRaw {
+    pub id: i32,
+    pub client_id: i32,
+    pub client_name: String,
+    pub from_date: NaiveDate,
+    pub to_date: NaiveDate,
+    pub time_zone: String,
+    pub rounding_minutes: i32,
+    pub rounding: String,
+    pub currency: String,
+    pub total_amount: i64,
+    pub issued_by: i32,
+    pub issued_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Invoice {
+    pub id: i32,
+    pub client_id: i32,
+    pub client_name: String,
+    pub from_date: NaiveDate,
+    pub to_date: NaiveDate,
+    pub time_zone: String,
+    pub rounding_minutes: i32,
+    pub rounding: String,
+    pub currency: String,
+    pub total_amount: i64,
+    pub issued_by: i32,
+    pub issued_at: DateTime<FixedOffset>,
+}
+
+impl Invoice {
+    pub fn from_raw(value: InvoiceRaw, tz: Tz) -> Self {
+        let InvoiceRaw {
+            id,
+            client_id,
+            client_name,
+            from_date,
+            to_date,
+            time_zone,
+            rounding_minutes,
+            rounding,
+            currency,
+            total_amount,
+            issued_by,
+            issued_at,
+        } = value;
+
+        Invoice {
+            id,
+            client_id,
+            client_name,
+            from_date,
+            to_date,
+            time_zone,
+            rounding_minutes,
+            rounding,
+            currency,
+            total_amount,
+            issued_by,
+            issued_at: into_timezone(issued_at, tz),
+        }
+    }
+}
+
+/// A billed gongzuo. Values are copied when the invoice is issued,
+/// so later changes to users, projects or rates don't affect issued invoices.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct InvoiceLineItemRaw {
+    pub gongzuo_id: i32,
+    pub user_id: i32,
+    pub username: String,
+    pub project_id: i32,
+    pub project_name: String,
+    pub content: String,
+    pub started_at: NaiveDateTime,
+    pub ended_at: NaiveDateTime,
+    /// Breaks are not counted.
+    pub worked_seconds: i64,
+    /// `worked_seconds` after rounding.
+    pub billed_seconds: i64,
+    pub hourly_rate: i64,
+    pub amount: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InvoiceLineItem {
+    pub gongzuo_id: i32,
+    pub user_id: i32,
+    pub username: String,
+    pub project_id: i32,
+    pub project_name: String,
+    pub content: String,
+    pub started_at: DateTime<FixedOffset>,
+    pub ended_at: DateTime<FixedOffset>,
+    pub worked_seconds: i64,
+    pub billed_seconds: i64,
+    pub hourly_rate: i64,
+    pub amount: i64,
+}
+
+impl InvoiceLineItem {
+    pub fn from_raw(value: InvoiceLineItemRaw, tz: Tz) -> Self {
+        let InvoiceLineItemRaw {
+            gongzuo_id,
+            user_id,
+            username,
+            project_id,
+            project_name,
+            content,
+            started_at,
+            ended_at,
+            worked_seconds,
+            billed_seconds,
+            hourly_rate,
+            amount,
+        } = value;
+
+        InvoiceLineItem {
+            gongzuo_id,
+            user_id,
+            username,
+            project_id,
+            project_name,
+            content,
+            started_at: into_timezone(started_at, tz),
+            ended_at: into_timezone(ended_at, tz),
+            worked_seconds,
+            billed_seconds,
+            hourly_rate,
+            amount,
+        }
+    }
+}
+
+pub struct InvoiceRequest {
+    pub client_id: i32,
+    /// First day of the invoice (inclusive) in `tz`.
+    pub from: NaiveDate,
+    /// Last day of the invoice (inclusive) in `tz`.
+    pub to: NaiveDate,
+    pub tz: Tz,
+    pub rounding_minutes: i32,
+    pub rounding: Rounding,
+}
+
+pub struct InvoiceDraft {
+    pub currency: String,
+    pub total_amount: i64,
+    pub line_items: Vec<InvoiceLineItemRaw>,
+}
+
+pub struct InvoiceHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> InvoiceHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait InvoiceHandlerTrait {
+    async fn invoices(&self, client_id: Option<i32>) -> Result<Vec<InvoiceRaw>>;
+    async fn invoice_by_id(&self, invoice_id: i32) -> Result<Option<InvoiceRaw>>;
+    async fn invoice_line_items(&self, invoice_id: i32) -> Result<Vec<InvoiceLineItemRaw>>;
+    /// Computes the invoice without issuing it.
+    async fn draft_invoice(&self, request: &InvoiceRequest) -> Result<InvoiceDraft>;
+    /// Issues the invoice and marks its gongzuos as invoiced. Returns the id of the invoice.
+    async fn issue_invoice(&self, request: &InvoiceRequest, issued_by: i32) -> Result<i32>;
+}
+
+#[axum::async_trait]
+impl InvoiceHandlerTrait for InvoiceHandler<'_> {
+    async fn invoices(&self, client_id: Option<i32>) -> Result<Vec<InvoiceRaw>> {
+        let invoices = sqlx::query_as!(
+            InvoiceRaw,
+            r#"
+            SELECT
+                invoices.id,
+                invoices.client_id,
+                clients.name AS client_name,
+                invoices.from_date,
+                invoices.to_date,
+                invoices.time_zone,
+                invoices.rounding_minutes,
+                invoices.rounding,
+                invoices.currency,
+                invoices.total_amount,
+                invoices.issued_by,
+                invoices.issued_at
+            FROM
+                invoices
+            JOIN
+                clients
+            ON
+                invoices.client_id = clients.id
+            WHERE
+                $1::INTEGER IS NULL OR invoices.client_id = $1
+            ORDER BY
+                invoices.issued_at DESC
+            "#,
+            client_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(invoices)
+    }
+
+    async fn invoice_by_id(&self, invoice_id: i32) -> Result<Option<InvoiceRaw>> {
+        let invoice = sqlx::query_as!(
+            InvoiceRaw,
+            r#"
+            SELECT
+                invoices.id,
+                invoices.client_id,
+                clients.name AS client_name,
+                invoices.from_date,
+                invoices.to_date,
+                invoices.time_zone,
+                invoices.rounding_minutes,
+                invoices.rounding,
+                invoices.currency,
+                invoices.total_amount,
+                invoices.issued_by,
+                invoices.issued_at
+            FROM
+                invoices
+            JOIN
+                clients
+            ON
+                invoices.client_id = clients.id
+            WHERE
+                invoices.id = $1
+            "#,
+            invoice_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(invoice)
+    }
+
+    async fn invoice_line_items(&self, invoice_id: i32) -> Result<Vec<InvoiceLineItemRaw>> {
+        let line_items = sqlx::query_as!(
+            InvoiceLineItemRaw,
+            r#"
+            SELECT
+                gongzuo_id,
+                user_id,
+                username,
+                project_id,
+                project_name,
+                content,
+                started_at,
+                ended_at,
+                worked_seconds,
+                billed_seconds,
+                hourly_rate,
+                amount
+            FROM
+                invoice_line_items
+            WHERE
+                invoice_id = $1
+            ORDER BY
+                id
+            "#,
+            invoice_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(line_items)
+    }
+
+    async fn draft_invoice(&self, request: &InvoiceRequest) -> Result<InvoiceDraft> {
+        let mut transaction = self.pool.begin().await?;
+
+        let draft = build_draft(&mut transaction, request).await?;
+
+        // 下書きは何も書き込まないので、ロックを外すだけ。
+        transaction.rollback().await?;
+
+        Ok(draft)
+    }
+
+    async fn issue_invoice(&self, request: &InvoiceRequest, issued_by: i32) -> Result<i32> {
+        let mut transaction = self.pool.begin().await?;
+
+        let InvoiceDraft {
+            currency,
+            total_amount,
+            line_items,
+        } = build_draft(&mut transaction, request).await?;
+
+        let invoice_id = sqlx::query!(
+            r#"
+            INSERT INTO invoices (
+                client_id,
+                from_date,
+                to_date,
+                time_zone,
+                rounding_minutes,
+                rounding,
+                currency,
+                total_amount,
+                issued_by
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING id
+            "#,
+            request.client_id,
+            request.from,
+            request.to,
+            request.tz.name(),
+            request.rounding_minutes,
+            request.rounding.as_str(),
+            currency,
+            total_amount,
+            issued_by
+        )
+        .fetch_one(&mut *transaction)
+        .await?
+        .id;
+
+        for line_item in &line_items {
+            sqlx::query!(
+                r#"
+                INSERT INTO invoice_line_items (
+                    invoice_id,
+                    gongzuo_id,
+                    user_id,
+                    username,
+                    project_id,
+                    project_name,
+                    content,
+                    started_at,
+                    ended_at,
+                    worked_seconds,
+                    billed_seconds,
+                    hourly_rate,
+                    amount
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                "#,
+                invoice_id,
+                line_item.gongzuo_id,
+                line_item.user_id,
+                line_item.username,
+                line_item.project_id,
+                line_item.project_name,
+                line_item.content,
+                line_item.started_at,
+                line_item.ended_at,
+                line_item.worked_seconds,
+                line_item.billed_seconds,
+                line_item.hourly_rate,
+                line_item.amount
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        let gongzuo_ids = line_items
+            .iter()
+            .map(|line_item| line_item.gongzuo_id)
+            .collect::<Vec<_>>();
+
+        sqlx::query!(
+            r#"
+            UPDATE
+                gongzuo
+            SET
+                invoice_id = $1
+            WHERE
+                id = ANY($2)
+            "#,
+            invoice_id,
+            &gongzuo_ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(invoice_id)
+    }
+}
+
+/// Collects ended, not yet invoiced work gongzuos of the client's projects which started within
+/// the range, and prices each of them with the most specific rate effective on its start date:
+/// the project-user pair, then the project, then the user.
+/// The gongzuos are locked until the transaction ends.
+async fn build_draft(conn: &mut PgConnection, request: &InvoiceRequest) -> Result<InvoiceDraft> {
+    let client = sqlx::query!(
+        r#"
+        SELECT
+            id
+        FROM
+            clients
+        WHERE
+            id = $1
+        "#,
+        request.client_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if client.is_none() {
+        return Err(AppError::NotFound(format!(
+            "Client {} not found",
+            request.client_id
+        )));
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            gongzuo.id AS gongzuo_id,
+            gongzuo.user_id,
+            users.username,
+            projects.id AS project_id,
+            projects.name AS project_name,
+            contents.content,
+            gongzuo.started_at,
+            gongzuo.ended_at AS "ended_at!",
+            COALESCE(
+                (
+                    SELECT
+                        json_agg(
+                            json_build_object(
+                                'started_at', gongzuo_breaks.started_at,
+                                'ended_at', gongzuo_breaks.ended_at
+                            )
+                            ORDER BY gongzuo_breaks.started_at
+                        )
+                    FROM
+                        gongzuo_breaks
+                    WHERE
+                        gongzuo_breaks.gongzuo_id = gongzuo.id
+                ),
+                '[]'
+            ) AS "breaks!: Json<Vec<BreakRaw>>",
+            rate.hourly_rate AS "hourly_rate?",
+            rate.currency AS "currency?"
+        FROM
+            gongzuo
+        JOIN
+            contents
+        ON
+            gongzuo.content_id = contents.id
+        JOIN
+            categories
+        ON
+            contents.category_id = categories.id
+        JOIN
+            projects
+        ON
+            gongzuo.project_id = projects.id
+        JOIN
+            users
+        ON
+            gongzuo.user_id = users.id
+        LEFT JOIN LATERAL (
+            SELECT
+                billable_rates.hourly_rate,
+                billable_rates.currency
+            FROM
+                billable_rates
+            WHERE
+                (billable_rates.project_id IS NULL OR billable_rates.project_id = gongzuo.project_id)
+            AND
+                (billable_rates.user_id IS NULL OR billable_rates.user_id = gongzuo.user_id)
+            AND
+                billable_rates.effective_from <= (gongzuo.started_at AT TIME ZONE 'UTC' AT TIME ZONE $4)::DATE
+            ORDER BY
+                billable_rates.project_id IS NOT NULL AND billable_rates.user_id IS NOT NULL DESC,
+                billable_rates.project_id IS NOT NULL DESC,
+                billable_rates.effective_from DESC
+            LIMIT 1
+        ) AS rate
+        ON
+            TRUE
+        WHERE
+            projects.client_id = $1
+        AND
+            categories.counts_as_work
+        AND
+            gongzuo.ended_at IS NOT NULL
+        AND
+            gongzuo.deleted_at IS NULL
+        AND
+            gongzuo.invoice_id IS NULL
+        AND
+            gongzuo.started_at >= $2
+        AND
+            gongzuo.started_at < $3
+        ORDER BY
+            gongzuo.started_at,
+            gongzuo.id
+        FOR UPDATE OF gongzuo
+        "#,
+        request.client_id,
+        start_of_day(request.from, request.tz),
+        start_of_day(request.to + chrono::Days::new(1), request.tz),
+        request.tz.name()
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if rows.is_empty() {
+        return Err(AppError::Validation(String::from(
+            "No billable gongzuos in the range",
+        )));
+    }
+
+    let mut currency = None;
+    let mut line_items = Vec::with_capacity(rows.len());
+    for row in rows {
+        let (Some(hourly_rate), Some(row_currency)) = (row.hourly_rate, row.currency) else {
+            return Err(AppError::Validation(format!(
+                "No billable rate for gongzuo {} (user {}, project {})",
+                row.gongzuo_id, row.user_id, row.project_id
+            )));
+        };
+
+        match &currency {
+            None => currency = Some(row_currency),
+            Some(currency) if *currency != row_currency => {
+                return Err(AppError::Validation(format!(
+                    "Rates of gongzuos in an invoice must share a currency: {} and {}",
+                    currency, row_currency
+                )))
+            }
+            Some(_) => {}
+        }
+
+        let worked_seconds = worked_periods(
+            row.started_at,
+            Some(row.ended_at),
+            &row.breaks,
+            row.ended_at,
+        )
+        .into_iter()
+        .map(|(started_at, ended_at)| (ended_at - started_at).num_seconds())
+        .sum::<i64>();
+        let billed_seconds =
+            round_seconds(worked_seconds, request.rounding_minutes, request.rounding);
+
+        line_items.push(InvoiceLineItemRaw {
+            gongzuo_id: row.gongzuo_id,
+            user_id: row.user_id,
+            username: row.username,
+            project_id: row.project_id,
+            project_name: row.project_name,
+            content: row.content,
+            started_at: row.started_at,
+            ended_at: row.ended_at,
+            worked_seconds,
+            billed_seconds,
+            hourly_rate,
+            amount: amount(billed_seconds, hourly_rate),
+        });
+    }
+
+    Ok(InvoiceDraft {
+        // rows が空でなければ必ず決まっている。
+        currency: currency.unwrap(),
+        total_amount: line_items.iter().map(|line_item| line_item.amount).sum(),
+        line_items,
+    })
+}
@@ -0,0 +1,197 @@
+use chrono::NaiveDateTime;
+use serde_json::json;
+use sqlx::Postgres;
+
+use crate::db::audit::{record_audit, AuditAction};
+use crate::error::{AppError, Result};
+
+/// What failed logins are counted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginScope {
+    /// The username which was tried, whether the user exists or not.
+    Username,
+    Ip,
+}
+
+impl LoginScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LoginScope::Username => "username",
+            LoginScope::Ip => "ip",
+        }
+    }
+}
+
+pub struct LoginFailureHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> LoginFailureHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait LoginFailureHandlerTrait {
+    /// The latest lockout of the username or the IP address which hasn't ended at `now`.
+    async fn locked_until(
+        &self,
+        username: &str,
+        ip: &str,
+        now: NaiveDateTime,
+    ) -> Result<Option<NaiveDateTime>>;
+    /// Counts a failed login and returns the number of failures in a row.
+    /// The count starts over if the last failure was before `reset_before`.
+    async fn record_failure(
+        &self,
+        scope: LoginScope,
+        key: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> Result<i32>;
+    async fn lock(&self, scope: LoginScope, key: &str, locked_until: NaiveDateTime) -> Result<()>;
+    /// Forgets the failures, e.g. after a successful login.
+    async fn clear(&self, scope: LoginScope, key: &str) -> Result<()>;
+    /// Lets the user log in again by forgetting the failures of their username.
+    /// Failures from IP addresses are kept. `actor_id` is the admin who unlocks.
+    async fn unlock_user(&self, user_id: i32, actor_id: i32) -> Result<()>;
+}
+
+#[axum::async_trait]
+impl LoginFailureHandlerTrait for LoginFailureHandler<'_> {
+    async fn locked_until(
+        &self,
+        username: &str,
+        ip: &str,
+        now: NaiveDateTime,
+    ) -> Result<Option<NaiveDateTime>> {
+        let locked_until = sqlx::query!(
+            r#"
+            SELECT
+                MAX(locked_until) AS locked_until
+            FROM
+                login_failures
+            WHERE
+                ((scope = 'username' AND key = $1) OR (scope = 'ip' AND key = $2))
+            AND
+                locked_until > $3
+            "#,
+            username,
+            ip,
+            now
+        )
+        .fetch_one(self.pool)
+        .await?
+        .locked_until;
+
+        Ok(locked_until)
+    }
+
+    async fn record_failure(
+        &self,
+        scope: LoginScope,
+        key: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> Result<i32> {
+        let failed_count = sqlx::query!(
+            r#"
+            INSERT INTO login_failures (scope, key, failed_count, last_failed_at)
+            VALUES ($1, $2, 1, $3)
+            ON CONFLICT (scope, key) DO UPDATE
+            SET
+                failed_count = CASE
+                    WHEN login_failures.last_failed_at < $4 THEN 1
+                    ELSE login_failures.failed_count + 1
+                END,
+                last_failed_at = $3
+            RETURNING failed_count
+            "#,
+            scope.as_str(),
+            key,
+            now,
+            reset_before
+        )
+        .fetch_one(self.pool)
+        .await?
+        .failed_count;
+
+        Ok(failed_count)
+    }
+
+    async fn lock(&self, scope: LoginScope, key: &str, locked_until: NaiveDateTime) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE login_failures
+            SET locked_until = $3
+            WHERE scope = $1 AND key = $2
+            "#,
+            scope.as_str(),
+            key,
+            locked_until
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn clear(&self, scope: LoginScope, key: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM login_failures
+            WHERE scope = $1 AND key = $2
+            "#,
+            scope.as_str(),
+            key
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unlock_user(&self, user_id: i32, actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let Some(user) = sqlx::query!(
+            r#"
+            SELECT username
+            FROM users
+            WHERE id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        else {
+            return Err(AppError::NotFound(format!("User {} not found", user_id)));
+        };
+
+        let failed_count = sqlx::query!(
+            r#"
+            DELETE FROM login_failures
+            WHERE scope = 'username' AND key = $1
+            RETURNING failed_count
+            "#,
+            user.username
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .map_or(0, |r| r.failed_count);
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserUnlock,
+            user_id,
+            json!({ "failed_count": failed_count }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,515 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Postgres};
+
+use crate::error::{AppError, Result};
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct Client {
+    pub id: i32,
+    pub name: String,
+    pub archived: bool,
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct Project {
+    pub id: i32,
+    pub client_id: i32,
+    pub client_name: String,
+    pub name: String,
+    /// Archived projects are kept for existing gongzuos but can't be used for new ones.
+    pub archived: bool,
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectMember {
+    pub user_id: i32,
+    pub username: String,
+}
+
+pub struct ClientPayload {
+    pub name: String,
+    pub archived: bool,
+}
+
+pub struct ProjectPayload {
+    pub client_id: i32,
+    pub name: String,
+    pub archived: bool,
+}
+
+/// Conditions for listing projects. `None` means "don't filter by this".
+#[derive(Debug, Clone, Default)]
+pub struct ProjectFilter {
+    pub client_id: Option<i32>,
+    /// Only projects which this user is a member of.
+    pub member_user_id: Option<i32>,
+    pub include_archived: bool,
+}
+
+pub struct ProjectHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> ProjectHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait ProjectHandlerTrait {
+    async fn clients(&self, include_archived: bool) -> Result<Vec<Client>>;
+    async fn create_client(&self, payload: ClientPayload) -> Result<Client>;
+    async fn update_client(&self, client_id: i32, payload: ClientPayload) -> Result<Client>;
+    /// Only clients without projects can be deleted. Archive the others instead.
+    async fn delete_client(&self, client_id: i32) -> Result<()>;
+    async fn projects(&self, filter: &ProjectFilter) -> Result<Vec<Project>>;
+    async fn project_by_id(&self, project_id: i32) -> Result<Option<Project>>;
+    async fn create_project(&self, payload: ProjectPayload) -> Result<Project>;
+    async fn update_project(&self, project_id: i32, payload: ProjectPayload) -> Result<Project>;
+    /// Only projects which no gongzuo uses can be deleted. Archive the others instead.
+    async fn delete_project(&self, project_id: i32) -> Result<()>;
+    async fn project_members(&self, project_id: i32) -> Result<Vec<ProjectMember>>;
+    /// Returns `false` if the user is already a member.
+    async fn add_project_member(&self, project_id: i32, user_id: i32) -> Result<bool>;
+    async fn remove_project_member(&self, project_id: i32, user_id: i32) -> Result<()>;
+}
+
+#[axum::async_trait]
+impl ProjectHandlerTrait for ProjectHandler<'_> {
+    async fn clients(&self, include_archived: bool) -> Result<Vec<Client>> {
+        let clients = sqlx::query_as!(
+            Client,
+            r#"
+            SELECT * FROM clients
+            WHERE $1 OR archived = false
+            ORDER BY name
+            "#,
+            include_archived
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(clients)
+    }
+
+    async fn create_client(&self, payload: ClientPayload) -> Result<Client> {
+        let ClientPayload { name, archived } = payload;
+
+        let client = sqlx::query_as!(
+            Client,
+            r#"
+            INSERT INTO clients (name, archived)
+            VALUES ($1, $2)
+            RETURNING *
+            "#,
+            name,
+            archived
+        )
+        .fetch_one(self.pool)
+        .await
+        .map_err(|e| map_unique_violation(e, format!("Client {} already exists", name)))?;
+
+        Ok(client)
+    }
+
+    async fn update_client(&self, client_id: i32, payload: ClientPayload) -> Result<Client> {
+        let ClientPayload { name, archived } = payload;
+
+        let client = sqlx::query_as!(
+            Client,
+            r#"
+            UPDATE clients
+            SET name = $1, archived = $2
+            WHERE id = $3
+            RETURNING *
+            "#,
+            name,
+            archived,
+            client_id
+        )
+        .fetch_optional(self.pool)
+        .await
+        .map_err(|e| map_unique_violation(e, format!("Client {} already exists", name)))?
+        .ok_or_else(|| AppError::NotFound(format!("Client {} not found", client_id)))?;
+
+        Ok(client)
+    }
+
+    async fn delete_client(&self, client_id: i32) -> Result<()> {
+        let has_projects = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM projects WHERE client_id = $1
+            ) AS "has_projects!"
+            "#,
+            client_id
+        )
+        .fetch_one(self.pool)
+        .await?
+        .has_projects;
+
+        if has_projects {
+            return Err(AppError::Conflict(format!(
+                "Client {} has projects. Archive it instead.",
+                client_id
+            )));
+        }
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM clients
+            WHERE id = $1
+            "#,
+            client_id
+        )
+        .execute(self.pool)
+        .await
+        // 確認してから消すまでの間にプロジェクトが作られた場合
+        .map_err(|e| {
+            map_foreign_key_violation(
+                e,
+                AppError::Conflict(format!(
+                    "Client {} has projects. Archive it instead.",
+                    client_id
+                )),
+            )
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Client {} not found",
+                client_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn projects(&self, filter: &ProjectFilter) -> Result<Vec<Project>> {
+        let projects = sqlx::query_as!(
+            Project,
+            r#"
+            SELECT
+                projects.id,
+                projects.client_id,
+                clients.name AS client_name,
+                projects.name,
+                projects.archived
+            FROM
+                projects
+            JOIN
+                clients
+            ON
+                projects.client_id = clients.id
+            WHERE
+                ($1::INTEGER IS NULL OR projects.client_id = $1)
+            AND
+                (
+                    $2::INTEGER IS NULL
+                OR
+                    EXISTS (
+                        SELECT 1 FROM project_members
+                        WHERE project_id = projects.id AND user_id = $2
+                    )
+                )
+            AND
+                ($3 OR projects.archived = false)
+            ORDER BY
+                clients.name, projects.name
+            "#,
+            filter.client_id,
+            filter.member_user_id,
+            filter.include_archived
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(projects)
+    }
+
+    async fn project_by_id(&self, project_id: i32) -> Result<Option<Project>> {
+        let project = sqlx::query_as!(
+            Project,
+            r#"
+            SELECT
+                projects.id,
+                projects.client_id,
+                clients.name AS client_name,
+                projects.name,
+                projects.archived
+            FROM
+                projects
+            JOIN
+                clients
+            ON
+                projects.client_id = clients.id
+            WHERE
+                projects.id = $1
+            "#,
+            project_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(project)
+    }
+
+    async fn create_project(&self, payload: ProjectPayload) -> Result<Project> {
+        let ProjectPayload {
+            client_id,
+            name,
+            archived,
+        } = payload;
+
+        let project_id = sqlx::query!(
+            r#"
+            INSERT INTO projects (client_id, name, archived)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            "#,
+            client_id,
+            name,
+            archived
+        )
+        .fetch_one(self.pool)
+        .await
+        .map_err(|e| map_project_error(e, client_id, &name))?
+        .id;
+
+        self.project_by_id(project_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Project {} not found", project_id)))
+    }
+
+    async fn update_project(&self, project_id: i32, payload: ProjectPayload) -> Result<Project> {
+        let ProjectPayload {
+            client_id,
+            name,
+            archived,
+        } = payload;
+
+        let updated = sqlx::query!(
+            r#"
+            UPDATE projects
+            SET client_id = $1, name = $2, archived = $3
+            WHERE id = $4
+            RETURNING id
+            "#,
+            client_id,
+            name,
+            archived,
+            project_id
+        )
+        .fetch_optional(self.pool)
+        .await
+        .map_err(|e| map_project_error(e, client_id, &name))?;
+
+        if updated.is_none() {
+            return Err(AppError::NotFound(format!(
+                "Project {} not found",
+                project_id
+            )));
+        }
+
+        self.project_by_id(project_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Project {} not found", project_id)))
+    }
+
+    async fn delete_project(&self, project_id: i32) -> Result<()> {
+        let in_use = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM gongzuo WHERE project_id = $1
+            ) AS "in_use!"
+            "#,
+            project_id
+        )
+        .fetch_one(self.pool)
+        .await?
+        .in_use;
+
+        if in_use {
+            return Err(AppError::Conflict(format!(
+                "Project {} is used by gongzuos. Archive it instead.",
+                project_id
+            )));
+        }
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM projects
+            WHERE id = $1
+            "#,
+            project_id
+        )
+        .execute(self.pool)
+        .await
+        .map_err(|e| {
+            map_foreign_key_violation(
+                e,
+                AppError::Conflict(format!(
+                    "Project {} is used by gongzuos. Archive it instead.",
+                    project_id
+                )),
+            )
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Project {} not found",
+                project_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn project_members(&self, project_id: i32) -> Result<Vec<ProjectMember>> {
+        let members = sqlx::query_as!(
+            ProjectMember,
+            r#"
+            SELECT
+                users.id AS user_id,
+                users.username
+            FROM
+                project_members
+            JOIN
+                users
+            ON
+                project_members.user_id = users.id
+            WHERE
+                project_members.project_id = $1
+            ORDER BY
+                users.username
+            "#,
+            project_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    async fn add_project_member(&self, project_id: i32, user_id: i32) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO project_members (project_id, user_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            project_id,
+            user_id
+        )
+        .execute(self.pool)
+        .await
+        .map_err(|e| {
+            map_foreign_key_violation(
+                e,
+                AppError::NotFound(format!(
+                    "Project {} or user {} not found",
+                    project_id, user_id
+                )),
+            )
+        })?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_project_member(&self, project_id: i32, user_id: i32) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM project_members
+            WHERE project_id = $1 AND user_id = $2
+            "#,
+            project_id,
+            user_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "User {} is not a member of project {}",
+                user_id, project_id
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Gongzuos can only be recorded for active projects which the user is a member of.
+pub async fn ensure_project_member(
+    conn: &mut PgConnection,
+    project_id: i32,
+    user_id: i32,
+) -> Result<()> {
+    let project = sqlx::query!(
+        r#"
+        SELECT
+            archived,
+            EXISTS (
+                SELECT 1 FROM project_members
+                WHERE project_id = projects.id AND user_id = $2
+            ) AS "is_member!"
+        FROM
+            projects
+        WHERE
+            id = $1
+        "#,
+        project_id,
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match project {
+        None => Err(AppError::Validation(format!(
+            "Project {} not found",
+            project_id
+        ))),
+        Some(project) if project.archived => Err(AppError::Validation(format!(
+            "Project {} is archived",
+            project_id
+        ))),
+        Some(project) if !project.is_member => Err(AppError::Forbidden(format!(
+            "You are not a member of project {}",
+            project_id
+        ))),
+        Some(_) => Ok(()),
+    }
+}
+
+fn map_project_error(error: sqlx::Error, client_id: i32, name: &str) -> AppError {
+    if let sqlx::Error::Database(database_error) = &error {
+        if database_error.code().as_deref() == Some("23503") {
+            return AppError::Validation(format!("Client {} not found", client_id));
+        }
+    }
+    map_unique_violation(
+        error,
+        format!("Project {} already exists for the client", name),
+    )
+}
+
+fn map_unique_violation(error: sqlx::Error, message: String) -> AppError {
+    if let sqlx::Error::Database(database_error) = &error {
+        // unique_violation
+        if database_error.code().as_deref() == Some("23505") {
+            return AppError::Conflict(message);
+        }
+    }
+    error.into()
+}
+
+fn map_foreign_key_violation(error: sqlx::Error, app_error: AppError) -> AppError {
+    if let sqlx::Error::Database(database_error) = &error {
+        // foreign_key_violation
+        if database_error.code().as_deref() == Some("23503") {
+            return app_error;
+        }
+    }
+    error.into()
+}
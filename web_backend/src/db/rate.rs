@@ -0,0 +1,156 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::Postgres;
+
+use crate::error::{AppError, Result};
+
+/// Hourly rate in the minor unit of `currency`.
+/// A rate stays effective from `effective_from` until the next rate of the same scope.
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct BillableRate {
+    pub id: i32,
+    pub project_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub hourly_rate: i64,
+    pub currency: String,
+    pub effective_from: NaiveDate,
+}
+
+pub struct BillableRatePayload {
+    pub project_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub hourly_rate: i64,
+    pub currency: String,
+    pub effective_from: NaiveDate,
+}
+
+pub struct RateHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> RateHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait RateHandlerTrait {
+    /// Rates sorted by scope and then by `effective_from`, newest first.
+    async fn rates(
+        &self,
+        project_id: Option<i32>,
+        user_id: Option<i32>,
+    ) -> Result<Vec<BillableRate>>;
+    async fn create_rate(&self, payload: BillableRatePayload) -> Result<BillableRate>;
+    async fn delete_rate(&self, rate_id: i32) -> Result<()>;
+}
+
+#[axum::async_trait]
+impl RateHandlerTrait for RateHandler<'_> {
+    async fn rates(
+        &self,
+        project_id: Option<i32>,
+        user_id: Option<i32>,
+    ) -> Result<Vec<BillableRate>> {
+        let rates = sqlx::query_as!(
+            BillableRate,
+            r#"
+            SELECT
+                id,
+                project_id,
+                user_id,
+                hourly_rate,
+                currency,
+                effective_from
+            FROM
+                billable_rates
+            WHERE
+                ($1::INTEGER IS NULL OR project_id = $1)
+            AND
+                ($2::INTEGER IS NULL OR user_id = $2)
+            ORDER BY
+                project_id NULLS FIRST,
+                user_id NULLS FIRST,
+                effective_from DESC
+            "#,
+            project_id,
+            user_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rates)
+    }
+
+    async fn create_rate(&self, payload: BillableRatePayload) -> Result<BillableRate> {
+        let BillableRatePayload {
+            project_id,
+            user_id,
+            hourly_rate,
+            currency,
+            effective_from,
+        } = payload;
+
+        let rate = sqlx::query_as!(
+            BillableRate,
+            r#"
+            INSERT INTO billable_rates (project_id, user_id, hourly_rate, currency, effective_from)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING
+                id,
+                project_id,
+                user_id,
+                hourly_rate,
+                currency,
+                effective_from
+            "#,
+            project_id,
+            user_id,
+            hourly_rate,
+            currency,
+            effective_from
+        )
+        .fetch_one(self.pool)
+        .await
+        .map_err(|error| {
+            if let sqlx::Error::Database(database_error) = &error {
+                match database_error.code().as_deref() {
+                    // unique_violation
+                    Some("23505") => {
+                        return AppError::Conflict(format!(
+                            "A rate effective from {} already exists for the scope",
+                            effective_from
+                        ))
+                    }
+                    // foreign_key_violation
+                    Some("23503") => {
+                        return AppError::Validation(String::from("Project or user not found"))
+                    }
+                    _ => {}
+                }
+            }
+            error.into()
+        })?;
+
+        Ok(rate)
+    }
+
+    async fn delete_rate(&self, rate_id: i32) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM billable_rates
+            WHERE id = $1
+            "#,
+            rate_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Rate {} not found", rate_id)));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{PgConnection, Postgres};
+
+use crate::db::gongzuo::{Break, BreakRaw};
+use crate::error::Result;
+use crate::util::timezone::into_timezone;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionAction {
+    Create,
+    Update,
+    /// Moved to the trash.
+    Delete,
+    /// Taken out of the trash.
+    Restore,
+    /// A break started.
+    Pause,
+    /// The ongoing break ended.
+    Resume,
+}
+
+impl RevisionAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RevisionAction::Create => "create",
+            RevisionAction::Update => "update",
+            RevisionAction::Delete => "delete",
+            RevisionAction::Restore => "restore",
+            RevisionAction::Pause => "pause",
+            RevisionAction::Resume => "resume",
+        }
+    }
+}
+
+/// Values of a gongzuo at some point, as stored in `gongzuo_revisions`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GongzuoSnapshotRaw {
+    /// Owner of the gongzuo. It never changes.
+    pub user_id: i32,
+    pub started_at: NaiveDateTime,
+    pub ended_at: Option<NaiveDateTime>,
+    pub category_id: i32,
+    pub content_id: i32,
+    pub content: String,
+    pub project_id: Option<i32>,
+    pub tags: Vec<String>,
+    /// Missing in revisions recorded before breaks were recorded.
+    #[serde(default)]
+    pub breaks: Json<Vec<BreakRaw>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GongzuoSnapshot {
+    pub user_id: i32,
+    pub started_at: DateTime<FixedOffset>,
+    pub ended_at: Option<DateTime<FixedOffset>>,
+    pub category_id: i32,
+    pub content_id: i32,
+    pub content: String,
+    pub project_id: Option<i32>,
+    pub tags: Vec<String>,
+    pub breaks: Vec<Break>,
+}
+
+impl GongzuoSnapshot {
+    pub fn from_raw(value: GongzuoSnapshotRaw, tz: Tz) -> Self {
+        let GongzuoSnapshotRaw {
+            user_id,
+            started_at,
+            ended_at,
+            category_id,
+            content_id,
+            content,
+            project_id,
+            tags,
+            breaks: Json(breaks),
+        } = value;
+
+        GongzuoSnapshot {
+            user_id,
+            started_at: into_timezone(started_at, tz),
+            ended_at: ended_at.map(|ended_at| into_timezone(ended_at, tz)),
+            category_id,
+            content_id,
+            content,
+            project_id,
+            tags,
+            breaks: breaks.into_iter().map(|b| Break::from_raw(b, tz)).collect(),
+        }
+    }
+}
+
+/// Names of the fields which differ between `before` and `after`.
+fn changed_fields(before: &GongzuoSnapshotRaw, after: &GongzuoSnapshotRaw) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if before.started_at != after.started_at {
+        changed.push("started_at");
+    }
+    if before.ended_at != after.ended_at {
+        changed.push("ended_at");
+    }
+    if before.category_id != after.category_id {
+        changed.push("category_id");
+    }
+    if before.content_id != after.content_id {
+        changed.push("content");
+    }
+    if before.project_id != after.project_id {
+        changed.push("project_id");
+    }
+    if before.tags != after.tags {
+        changed.push("tags");
+    }
+    if before.breaks != after.breaks {
+        changed.push("breaks");
+    }
+    changed
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct GongzuoRevisionRaw {
+    pub id: i32,
+    pub gongzuo_id: i32,
+    pub user_id: i32,
+    pub username: String,
+    pub action: String,
+    pub revised_at: NaiveDateTime,
+    pub before: Option<Json<GongzuoSnapshotRaw>>,
+    pub after: Option<Json<GongzuoSnapshotRaw>>,
+}
+
+impl GongzuoRevisionRaw {
+    /// Owner of the revised gongzuo, who isn't necessarily the user who made the change.
+    pub fn owner_id(&self) -> Option<i32> {
+        self.after
+            .as_ref()
+            .or(self.before.as_ref())
+            .map(|snapshot| snapshot.user_id)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct GongzuoRevision {
+    pub id: i32,
+    pub gongzuo_id: i32,
+    /// The user who made the change.
+    pub user_id: i32,
+    pub username: String,
+    pub action: String,
+    pub revised_at: DateTime<FixedOffset>,
+    pub before: Option<GongzuoSnapshot>,
+    pub after: Option<GongzuoSnapshot>,
+    /// Fields changed by an update, pause or resume. Empty for the other actions.
+    pub changed: Vec<&'static str>,
+}
+
+impl GongzuoRevision {
+    pub fn from_raw(value: GongzuoRevisionRaw, tz: Tz) -> Self {
+        let GongzuoRevisionRaw {
+            id,
+            gongzuo_id,
+            user_id,
+            username,
+            action,
+            revised_at,
+            before,
+            after,
+        } = value;
+
+        let changed = match (&before, &after) {
+            (Some(before), Some(after)) => changed_fields(before, after),
+            _ => Vec::new(),
+        };
+
+        GongzuoRevision {
+            id,
+            gongzuo_id,
+            user_id,
+            username,
+            action,
+            revised_at: into_timezone(revised_at, tz),
+            before: before.map(|Json(before)| GongzuoSnapshot::from_raw(before, tz)),
+            after: after.map(|Json(after)| GongzuoSnapshot::from_raw(after, tz)),
+            changed,
+        }
+    }
+}
+
+pub struct RevisionHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> RevisionHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait RevisionHandlerTrait {
+    /// Revisions of the gongzuo, oldest first.
+    /// They are kept after the gongzuo is purged from the trash.
+    async fn gongzuo_revisions(&self, gongzuo_id: i32) -> Result<Vec<GongzuoRevisionRaw>>;
+}
+
+#[axum::async_trait]
+impl RevisionHandlerTrait for RevisionHandler<'_> {
+    async fn gongzuo_revisions(&self, gongzuo_id: i32) -> Result<Vec<GongzuoRevisionRaw>> {
+        let revisions = sqlx::query_as!(
+            GongzuoRevisionRaw,
+            r#"
+            SELECT
+                gongzuo_revisions.id,
+                gongzuo_revisions.gongzuo_id,
+                gongzuo_revisions.user_id,
+                users.username,
+                gongzuo_revisions.action,
+                gongzuo_revisions.revised_at,
+                gongzuo_revisions.before AS "before: Json<GongzuoSnapshotRaw>",
+                gongzuo_revisions.after AS "after: Json<GongzuoSnapshotRaw>"
+            FROM
+                gongzuo_revisions
+            JOIN
+                users
+            ON
+                gongzuo_revisions.user_id = users.id
+            WHERE
+                gongzuo_revisions.gongzuo_id = $1
+            ORDER BY
+                gongzuo_revisions.id
+            "#,
+            gongzuo_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(revisions)
+    }
+}
+
+/// Current values of the gongzuo, including one in the trash.
+pub async fn gongzuo_snapshot(
+    conn: &mut PgConnection,
+    gongzuo_id: i32,
+) -> Result<Option<GongzuoSnapshotRaw>> {
+    let snapshot = sqlx::query_as!(
+        GongzuoSnapshotRaw,
+        r#"
+        SELECT
+            user_id AS "user_id!",
+            started_at AS "started_at!",
+            ended_at,
+            category_id AS "category_id!",
+            content_id AS "content_id!",
+            content AS "content!",
+            project_id,
+            tags AS "tags!",
+            breaks AS "breaks!: Json<Vec<BreakRaw>>"
+        FROM
+            gongzuo_details
+        WHERE
+            id = $1
+        "#,
+        gongzuo_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(snapshot)
+}
+
+/// Appends a revision. Call this in the transaction which makes the change.
+pub async fn record_revision(
+    conn: &mut PgConnection,
+    gongzuo_id: i32,
+    user_id: i32,
+    action: RevisionAction,
+    before: Option<GongzuoSnapshotRaw>,
+    after: Option<GongzuoSnapshotRaw>,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            gongzuo_revisions (gongzuo_id, user_id, action, revised_at, before, after)
+        VALUES
+            ($1, $2, $3, $4, $5, $6)
+        "#,
+        gongzuo_id,
+        user_id,
+        action.as_str(),
+        Utc::now().naive_utc(),
+        before.map(Json) as Option<Json<GongzuoSnapshotRaw>>,
+        after.map(Json) as Option<Json<GongzuoSnapshotRaw>>
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,374 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Postgres;
+
+use crate::db::audit::{record_audit, AuditAction};
+use crate::error::{AppError, Result};
+
+/// What a role allows. Admins have every permission without any role.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Register new users.
+    #[serde(rename = "users.create")]
+    UsersCreate,
+    /// Edit gongzuos of other users.
+    #[serde(rename = "gongzuo.edit_others")]
+    GongzuoEditOthers,
+    /// Read the reports of the teams the user belongs to.
+    #[serde(rename = "reports.view_team")]
+    ReportsViewTeam,
+    /// Approve timesheets. Nothing checks it until timesheets are submitted for approval.
+    #[serde(rename = "timesheets.approve")]
+    TimesheetsApprove,
+}
+
+impl Permission {
+    /// Every permission, sorted by name.
+    pub const ALL: [Permission; 4] = [
+        Permission::GongzuoEditOthers,
+        Permission::ReportsViewTeam,
+        Permission::TimesheetsApprove,
+        Permission::UsersCreate,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::UsersCreate => "users.create",
+            Permission::GongzuoEditOthers => "gongzuo.edit_others",
+            Permission::ReportsViewTeam => "reports.view_team",
+            Permission::TimesheetsApprove => "timesheets.approve",
+        }
+    }
+
+    pub fn parse(permission: &str) -> Option<Self> {
+        match permission {
+            "users.create" => Some(Permission::UsersCreate),
+            "gongzuo.edit_others" => Some(Permission::GongzuoEditOthers),
+            "reports.view_team" => Some(Permission::ReportsViewTeam),
+            "timesheets.approve" => Some(Permission::TimesheetsApprove),
+            _ => None,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct Role {
+    pub id: i32,
+    pub name: String,
+    /// Sorted by name.
+    pub permissions: Vec<String>,
+}
+
+pub struct RoleHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> RoleHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait RoleHandlerTrait {
+    /// Roles sorted by name. If `user_id` is given, only roles assigned to the user.
+    async fn roles(&self, user_id: Option<i32>) -> Result<Vec<Role>>;
+    async fn role_by_id(&self, role_id: i32) -> Result<Option<Role>>;
+    async fn create_role(&self, name: &str, permissions: &[Permission]) -> Result<Role>;
+    /// Replaces the name and the permissions of the role.
+    async fn update_role(
+        &self,
+        role_id: i32,
+        name: &str,
+        permissions: &[Permission],
+    ) -> Result<Role>;
+    /// Also unassigns the role from its users.
+    async fn delete_role(&self, role_id: i32) -> Result<()>;
+    /// Replaces the roles assigned to the user. `actor_id` is the admin who assigns them.
+    async fn set_user_roles(&self, user_id: i32, role_ids: &[i32], actor_id: i32) -> Result<()>;
+    /// Whether any role of the user grants the permission. `is_admin` isn't taken into account.
+    async fn has_permission(&self, user_id: i32, permission: Permission) -> Result<bool>;
+    /// Permissions granted by the roles of the user, sorted by name.
+    async fn user_permissions(&self, user_id: i32) -> Result<Vec<String>>;
+}
+
+#[axum::async_trait]
+impl RoleHandlerTrait for RoleHandler<'_> {
+    async fn roles(&self, user_id: Option<i32>) -> Result<Vec<Role>> {
+        let roles = sqlx::query_as!(
+            Role,
+            r#"
+            SELECT
+                roles.id,
+                roles.name,
+                ARRAY(
+                    SELECT permission FROM role_permissions
+                    WHERE role_permissions.role_id = roles.id
+                    ORDER BY permission
+                ) AS "permissions!"
+            FROM
+                roles
+            WHERE
+                $1::INTEGER IS NULL
+            OR
+                EXISTS (
+                    SELECT 1 FROM user_roles
+                    WHERE user_roles.role_id = roles.id AND user_roles.user_id = $1
+                )
+            ORDER BY
+                roles.name
+            "#,
+            user_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(roles)
+    }
+
+    async fn role_by_id(&self, role_id: i32) -> Result<Option<Role>> {
+        let role = sqlx::query_as!(
+            Role,
+            r#"
+            SELECT
+                roles.id,
+                roles.name,
+                ARRAY(
+                    SELECT permission FROM role_permissions
+                    WHERE role_permissions.role_id = roles.id
+                    ORDER BY permission
+                ) AS "permissions!"
+            FROM
+                roles
+            WHERE
+                roles.id = $1
+            "#,
+            role_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(role)
+    }
+
+    async fn create_role(&self, name: &str, permissions: &[Permission]) -> Result<Role> {
+        let mut transaction = self.pool.begin().await?;
+
+        let role_id = sqlx::query!(
+            r#"
+            INSERT INTO roles (name)
+            VALUES ($1)
+            RETURNING id
+            "#,
+            name
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| map_unique_violation(e, format!("Role {} already exists", name)))?
+        .id;
+
+        set_role_permissions(&mut transaction, role_id, permissions).await?;
+
+        transaction.commit().await?;
+
+        self.role_by_id(role_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Role {} not found", role_id)))
+    }
+
+    async fn update_role(
+        &self,
+        role_id: i32,
+        name: &str,
+        permissions: &[Permission],
+    ) -> Result<Role> {
+        let mut transaction = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE roles
+            SET name = $1
+            WHERE id = $2
+            "#,
+            name,
+            role_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| map_unique_violation(e, format!("Role {} already exists", name)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Role {} not found", role_id)));
+        }
+
+        set_role_permissions(&mut transaction, role_id, permissions).await?;
+
+        transaction.commit().await?;
+
+        self.role_by_id(role_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Role {} not found", role_id)))
+    }
+
+    async fn delete_role(&self, role_id: i32) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM roles
+            WHERE id = $1
+            "#,
+            role_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Role {} not found", role_id)));
+        }
+
+        Ok(())
+    }
+
+    async fn set_user_roles(&self, user_id: i32, role_ids: &[i32], actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM user_roles
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO user_roles (user_id, role_id)
+            SELECT $1, role_id FROM UNNEST($2::INTEGER[]) AS role_id
+            ON CONFLICT DO NOTHING
+            "#,
+            user_id,
+            role_ids
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            if let sqlx::Error::Database(database_error) = &e {
+                // foreign_key_violation
+                if database_error.code().as_deref() == Some("23503") {
+                    return AppError::NotFound(String::from("User or role not found"));
+                }
+            }
+            e.into()
+        })?;
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserRolesUpdate,
+            user_id,
+            json!({ "role_ids": role_ids }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn has_permission(&self, user_id: i32, permission: Permission) -> Result<bool> {
+        let has_permission = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM
+                    user_roles
+                JOIN
+                    role_permissions
+                ON
+                    user_roles.role_id = role_permissions.role_id
+                WHERE
+                    user_roles.user_id = $1
+                AND
+                    role_permissions.permission = $2
+            ) AS "has_permission!"
+            "#,
+            user_id,
+            permission.as_str()
+        )
+        .fetch_one(self.pool)
+        .await?
+        .has_permission;
+
+        Ok(has_permission)
+    }
+
+    async fn user_permissions(&self, user_id: i32) -> Result<Vec<String>> {
+        let permissions = sqlx::query!(
+            r#"
+            SELECT DISTINCT
+                role_permissions.permission
+            FROM
+                user_roles
+            JOIN
+                role_permissions
+            ON
+                user_roles.role_id = role_permissions.role_id
+            WHERE
+                user_roles.user_id = $1
+            ORDER BY
+                role_permissions.permission
+            "#,
+            user_id
+        )
+        .fetch_all(self.pool)
+        .await?
+        .into_iter()
+        .map(|r| r.permission)
+        .collect();
+
+        Ok(permissions)
+    }
+}
+
+async fn set_role_permissions(
+    conn: &mut sqlx::PgConnection,
+    role_id: i32,
+    permissions: &[Permission],
+) -> Result<()> {
+    let permissions: Vec<&str> = permissions.iter().map(|p| p.as_str()).collect();
+
+    sqlx::query!(
+        r#"
+        DELETE FROM role_permissions
+        WHERE role_id = $1
+        "#,
+        role_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO role_permissions (role_id, permission)
+        SELECT $1, permission FROM UNNEST($2::VARCHAR[]) AS permission
+        ON CONFLICT DO NOTHING
+        "#,
+        role_id,
+        &permissions as &[&str]
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+fn map_unique_violation(error: sqlx::Error, message: String) -> AppError {
+    if let sqlx::Error::Database(database_error) = &error {
+        // unique_violation
+        if database_error.code().as_deref() == Some("23505") {
+            return AppError::Conflict(message);
+        }
+    }
+    error.into()
+}
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Postgres};
+
+use crate::error::{AppError, Result};
+
+const MAX_TAG_LENGTH: usize = 64;
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    /// Number of gongzuos which have this tag.
+    pub gongzuo_count: i64,
+}
+
+pub struct TagHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> TagHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait TagHandlerTrait {
+    /// Tags which are attached to at least one gongzuo, sorted by name.
+    /// If `user_id` is given, only gongzuos of the user are counted.
+    async fn tags(&self, user_id: Option<i32>) -> Result<Vec<Tag>>;
+}
+
+#[axum::async_trait]
+impl TagHandlerTrait for TagHandler<'_> {
+    async fn tags(&self, user_id: Option<i32>) -> Result<Vec<Tag>> {
+        let tags = sqlx::query_as!(
+            Tag,
+            r#"
+            SELECT
+                tags.id,
+                tags.name,
+                COUNT(*) AS "gongzuo_count!"
+            FROM
+                tags
+            JOIN
+                gongzuo_tags
+            ON
+                tags.id = gongzuo_tags.tag_id
+            JOIN
+                gongzuo
+            ON
+                gongzuo_tags.gongzuo_id = gongzuo.id
+            WHERE
+                gongzuo.deleted_at IS NULL
+            AND
+                ($1::INTEGER IS NULL OR gongzuo.user_id = $1)
+            GROUP BY
+                tags.id
+            ORDER BY
+                tags.name
+            "#,
+            user_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+}
+
+/// Tags are stored without the leading `#`, so `#review` and `review` are the same tag.
+pub fn normalize_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim();
+    let tag = tag.strip_prefix('#').unwrap_or(tag);
+
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(AppError::Validation(format!(
+            "A tag must be between 1 and {} characters",
+            MAX_TAG_LENGTH
+        )));
+    }
+    if tag
+        .chars()
+        .any(|c| c.is_whitespace() || c == ',' || c == '#')
+    {
+        return Err(AppError::Validation(format!(
+            "A tag must not contain whitespace, ',' or '#': {}",
+            tag
+        )));
+    }
+
+    Ok(tag.to_string())
+}
+
+/// Normalizes `tags` and removes duplicates.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>> {
+    let mut tags = tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<Result<Vec<_>>>()?;
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
+
+/// Parses a comma-separated tag list such as `review,#clientA` given in a query string.
+pub fn parse_tag_list(tags: &str) -> Result<Vec<String>> {
+    let tags = tags.split(',').map(String::from).collect::<Vec<_>>();
+    normalize_tags(&tags)
+}
+
+/// Replaces the tags of the gongzuo with `tags`, creating tags which don't exist yet.
+pub async fn set_gongzuo_tags(
+    conn: &mut PgConnection,
+    gongzuo_id: i32,
+    tags: &[String],
+) -> Result<()> {
+    let tags = normalize_tags(tags)?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            tags (name)
+        SELECT
+            unnest($1::TEXT[])
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        &tags
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            gongzuo_tags
+        WHERE
+            gongzuo_id = $1
+        "#,
+        gongzuo_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            gongzuo_tags (gongzuo_id, tag_id)
+        SELECT
+            $1, id
+        FROM
+            tags
+        WHERE
+            name = ANY($2)
+        "#,
+        gongzuo_id,
+        &tags
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,557 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::Postgres;
+
+use crate::error::{AppError, Result};
+
+/// Roles are ordered by what they can do: `Member < Manager < Owner`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum TeamRole {
+    Member,
+    /// Can invite and remove members.
+    Manager,
+    /// Can also rename or delete the team and change roles.
+    Owner,
+}
+
+impl TeamRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TeamRole::Member => "member",
+            TeamRole::Manager => "manager",
+            TeamRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "member" => Some(TeamRole::Member),
+            "manager" => Some(TeamRole::Manager),
+            "owner" => Some(TeamRole::Owner),
+            _ => None,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct Team {
+    pub id: i32,
+    pub name: String,
+    pub member_count: i64,
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct TeamMember {
+    pub user_id: i32,
+    pub username: String,
+    pub role: String,
+}
+
+impl TeamMember {
+    pub fn role(&self) -> TeamRole {
+        TeamRole::parse(&self.role).unwrap_or(TeamRole::Member)
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct TeamInvitation {
+    pub id: i32,
+    pub team_id: i32,
+    pub team_name: String,
+    /// The invited user.
+    pub user_id: i32,
+    pub username: String,
+    pub role: String,
+    pub invited_by: i32,
+}
+
+pub struct TeamHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> TeamHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait TeamHandlerTrait {
+    /// Teams sorted by name. If `member_user_id` is given, only teams of the user.
+    async fn teams(&self, member_user_id: Option<i32>) -> Result<Vec<Team>>;
+    async fn team_by_id(&self, team_id: i32) -> Result<Option<Team>>;
+    /// Creates a team with `owner_id` as its first owner.
+    async fn create_team(&self, name: &str, owner_id: i32) -> Result<Team>;
+    async fn rename_team(&self, team_id: i32, name: &str) -> Result<Team>;
+    async fn delete_team(&self, team_id: i32) -> Result<()>;
+    async fn team_members(&self, team_id: i32) -> Result<Vec<TeamMember>>;
+    /// `None` if the user isn't a member of the team.
+    async fn team_role(&self, team_id: i32, user_id: i32) -> Result<Option<TeamRole>>;
+    /// Fails if this would leave the team without an owner.
+    async fn update_team_role(&self, team_id: i32, user_id: i32, role: TeamRole) -> Result<()>;
+    /// Fails if this would leave the team without an owner.
+    async fn remove_team_member(&self, team_id: i32, user_id: i32) -> Result<()>;
+    async fn invite_to_team(
+        &self,
+        team_id: i32,
+        user_id: i32,
+        role: TeamRole,
+        invited_by: i32,
+    ) -> Result<TeamInvitation>;
+    /// Pending invitations, of a team if `team_id` is given, to a user if `user_id` is given.
+    async fn team_invitations(
+        &self,
+        team_id: Option<i32>,
+        user_id: Option<i32>,
+    ) -> Result<Vec<TeamInvitation>>;
+    async fn team_invitation_by_id(&self, invitation_id: i32) -> Result<Option<TeamInvitation>>;
+    /// Makes the invited user a member and removes the invitation.
+    async fn accept_team_invitation(&self, invitation_id: i32) -> Result<()>;
+    async fn delete_team_invitation(&self, invitation_id: i32) -> Result<()>;
+}
+
+#[axum::async_trait]
+impl TeamHandlerTrait for TeamHandler<'_> {
+    async fn teams(&self, member_user_id: Option<i32>) -> Result<Vec<Team>> {
+        let teams = sqlx::query_as!(
+            Team,
+            r#"
+            SELECT
+                teams.id,
+                teams.name,
+                (
+                    SELECT COUNT(*) FROM team_memberships
+                    WHERE team_memberships.team_id = teams.id
+                ) AS "member_count!"
+            FROM
+                teams
+            WHERE
+                $1::INTEGER IS NULL
+            OR
+                EXISTS (
+                    SELECT 1 FROM team_memberships
+                    WHERE team_memberships.team_id = teams.id AND team_memberships.user_id = $1
+                )
+            ORDER BY
+                teams.name
+            "#,
+            member_user_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(teams)
+    }
+
+    async fn team_by_id(&self, team_id: i32) -> Result<Option<Team>> {
+        let team = sqlx::query_as!(
+            Team,
+            r#"
+            SELECT
+                teams.id,
+                teams.name,
+                (
+                    SELECT COUNT(*) FROM team_memberships
+                    WHERE team_memberships.team_id = teams.id
+                ) AS "member_count!"
+            FROM
+                teams
+            WHERE
+                teams.id = $1
+            "#,
+            team_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(team)
+    }
+
+    async fn create_team(&self, name: &str, owner_id: i32) -> Result<Team> {
+        let now = Utc::now().naive_utc();
+
+        let mut transaction = self.pool.begin().await?;
+
+        let team_id = sqlx::query!(
+            r#"
+            INSERT INTO teams (name, created_at)
+            VALUES ($1, $2)
+            RETURNING id
+            "#,
+            name,
+            now
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| map_unique_violation(e, format!("Team {} already exists", name)))?
+        .id;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO team_memberships (team_id, user_id, role, joined_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            team_id,
+            owner_id,
+            TeamRole::Owner.as_str(),
+            now
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(Team {
+            id: team_id,
+            name: name.to_string(),
+            member_count: 1,
+        })
+    }
+
+    async fn rename_team(&self, team_id: i32, name: &str) -> Result<Team> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE teams
+            SET name = $1
+            WHERE id = $2
+            "#,
+            name,
+            team_id
+        )
+        .execute(self.pool)
+        .await
+        .map_err(|e| map_unique_violation(e, format!("Team {} already exists", name)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Team {} not found", team_id)));
+        }
+
+        self.team_by_id(team_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Team {} not found", team_id)))
+    }
+
+    async fn delete_team(&self, team_id: i32) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM teams
+            WHERE id = $1
+            "#,
+            team_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Team {} not found", team_id)));
+        }
+
+        Ok(())
+    }
+
+    async fn team_members(&self, team_id: i32) -> Result<Vec<TeamMember>> {
+        let members = sqlx::query_as!(
+            TeamMember,
+            r#"
+            SELECT
+                users.id AS user_id,
+                users.username,
+                team_memberships.role
+            FROM
+                team_memberships
+            JOIN
+                users
+            ON
+                team_memberships.user_id = users.id
+            WHERE
+                team_memberships.team_id = $1
+            ORDER BY
+                users.username
+            "#,
+            team_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    async fn team_role(&self, team_id: i32, user_id: i32) -> Result<Option<TeamRole>> {
+        let role = sqlx::query!(
+            r#"
+            SELECT role FROM team_memberships
+            WHERE team_id = $1 AND user_id = $2
+            "#,
+            team_id,
+            user_id
+        )
+        .fetch_optional(self.pool)
+        .await?
+        .and_then(|r| TeamRole::parse(&r.role));
+
+        Ok(role)
+    }
+
+    async fn update_team_role(&self, team_id: i32, user_id: i32, role: TeamRole) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE team_memberships
+            SET role = $3
+            WHERE team_id = $1 AND user_id = $2
+            "#,
+            team_id,
+            user_id,
+            role.as_str()
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "User {} is not a member of team {}",
+                user_id, team_id
+            )));
+        }
+
+        ensure_team_has_owner(&mut transaction, team_id).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn remove_team_member(&self, team_id: i32, user_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM team_memberships
+            WHERE team_id = $1 AND user_id = $2
+            "#,
+            team_id,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "User {} is not a member of team {}",
+                user_id, team_id
+            )));
+        }
+
+        ensure_team_has_owner(&mut transaction, team_id).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn invite_to_team(
+        &self,
+        team_id: i32,
+        user_id: i32,
+        role: TeamRole,
+        invited_by: i32,
+    ) -> Result<TeamInvitation> {
+        if self.team_role(team_id, user_id).await?.is_some() {
+            return Err(AppError::Conflict(format!(
+                "User {} is already a member of team {}",
+                user_id, team_id
+            )));
+        }
+
+        let invitation_id = sqlx::query!(
+            r#"
+            INSERT INTO team_invitations (team_id, user_id, role, invited_by, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+            "#,
+            team_id,
+            user_id,
+            role.as_str(),
+            invited_by,
+            Utc::now().naive_utc()
+        )
+        .fetch_one(self.pool)
+        .await
+        .map_err(|e| {
+            map_unique_violation(
+                e,
+                format!("User {} is already invited to team {}", user_id, team_id),
+            )
+        })?
+        .id;
+
+        self.team_invitation_by_id(invitation_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Invitation {} not found", invitation_id)))
+    }
+
+    async fn team_invitations(
+        &self,
+        team_id: Option<i32>,
+        user_id: Option<i32>,
+    ) -> Result<Vec<TeamInvitation>> {
+        let invitations = sqlx::query_as!(
+            TeamInvitation,
+            r#"
+            SELECT
+                team_invitations.id,
+                team_invitations.team_id,
+                teams.name AS team_name,
+                team_invitations.user_id,
+                users.username,
+                team_invitations.role,
+                team_invitations.invited_by
+            FROM
+                team_invitations
+            JOIN
+                teams
+            ON
+                team_invitations.team_id = teams.id
+            JOIN
+                users
+            ON
+                team_invitations.user_id = users.id
+            WHERE
+                ($1::INTEGER IS NULL OR team_invitations.team_id = $1)
+            AND
+                ($2::INTEGER IS NULL OR team_invitations.user_id = $2)
+            ORDER BY
+                team_invitations.id
+            "#,
+            team_id,
+            user_id
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(invitations)
+    }
+
+    async fn team_invitation_by_id(&self, invitation_id: i32) -> Result<Option<TeamInvitation>> {
+        let invitation = sqlx::query_as!(
+            TeamInvitation,
+            r#"
+            SELECT
+                team_invitations.id,
+                team_invitations.team_id,
+                teams.name AS team_name,
+                team_invitations.user_id,
+                users.username,
+                team_invitations.role,
+                team_invitations.invited_by
+            FROM
+                team_invitations
+            JOIN
+                teams
+            ON
+                team_invitations.team_id = teams.id
+            JOIN
+                users
+            ON
+                team_invitations.user_id = users.id
+            WHERE
+                team_invitations.id = $1
+            "#,
+            invitation_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(invitation)
+    }
+
+    async fn accept_team_invitation(&self, invitation_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let invitation = sqlx::query!(
+            r#"
+            DELETE FROM team_invitations
+            WHERE id = $1
+            RETURNING team_id, user_id, role
+            "#,
+            invitation_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Invitation {} not found", invitation_id)))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO team_memberships (team_id, user_id, role, joined_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#,
+            invitation.team_id,
+            invitation.user_id,
+            invitation.role,
+            Utc::now().naive_utc()
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_team_invitation(&self, invitation_id: i32) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM team_invitations
+            WHERE id = $1
+            "#,
+            invitation_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Invitation {} not found",
+                invitation_id
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+async fn ensure_team_has_owner(conn: &mut sqlx::PgConnection, team_id: i32) -> Result<()> {
+    let owners = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!" FROM team_memberships
+        WHERE team_id = $1 AND role = $2
+        "#,
+        team_id,
+        TeamRole::Owner.as_str()
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .count;
+
+    if owners == 0 {
+        return Err(AppError::Conflict(format!(
+            "Team {} must have at least one owner",
+            team_id
+        )));
+    }
+
+    Ok(())
+}
+
+fn map_unique_violation(error: sqlx::Error, message: String) -> AppError {
+    if let sqlx::Error::Database(database_error) = &error {
+        // unique_violation
+        if database_error.code().as_deref() == Some("23505") {
+            return AppError::Conflict(message);
+        }
+    }
+    error.into()
+}
@@ -0,0 +1,310 @@
+use chrono::{NaiveDateTime, Utc};
+use serde_json::json;
+use sqlx::Postgres;
+
+use crate::db::audit::{record_audit, AuditAction};
+use crate::error::{AppError, Result};
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct TotpCredentialRaw {
+    pub user_id: i32,
+    pub secret: String,
+    pub created_at: NaiveDateTime,
+    /// `None` until the user confirms the enrollment with a code.
+    pub confirmed_at: Option<NaiveDateTime>,
+    pub last_used_step: Option<i64>,
+    pub recovery_codes_remaining: i64,
+}
+
+pub struct TotpHandler<'a> {
+    pool: &'a sqlx::Pool<Postgres>,
+}
+
+impl<'a> TotpHandler<'a> {
+    pub fn new(pool: &'a sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[axum::async_trait]
+pub trait TotpHandlerTrait {
+    /// Confirmed or still being enrolled.
+    async fn totp_credential(&self, user_id: i32) -> Result<Option<TotpCredentialRaw>>;
+    /// Starts over the enrollment with a new secret.
+    /// Fails with `Conflict` once the enrollment is confirmed.
+    async fn begin_enrollment(&self, user_id: i32, secret: &str) -> Result<()>;
+    /// Enables TOTP for the user and replaces the recovery codes.
+    /// `step` is of the code which confirmed it.
+    async fn confirm_enrollment(
+        &self,
+        user_id: i32,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<()>;
+    /// Returns false if the step isn't newer than the last used one, i.e. the code was used already.
+    async fn use_step(&self, user_id: i32, step: i64) -> Result<bool>;
+    /// Returns false if the code is wrong or used already.
+    async fn use_recovery_code(&self, user_id: i32, code_hash: &str) -> Result<bool>;
+    /// Turns TOTP off and deletes the secret and the recovery codes.
+    /// `actor_id` is the user themselves or an admin.
+    async fn disable_totp(&self, user_id: i32, actor_id: i32) -> Result<()>;
+    async fn create_login_challenge(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> Result<()>;
+    /// Deletes the challenge and returns the user who passed the password step,
+    /// if the challenge hasn't expired. A challenge can be used only once.
+    async fn consume_login_challenge(&self, token_hash: &str) -> Result<Option<i32>>;
+}
+
+#[axum::async_trait]
+impl TotpHandlerTrait for TotpHandler<'_> {
+    async fn totp_credential(&self, user_id: i32) -> Result<Option<TotpCredentialRaw>> {
+        let credential = sqlx::query_as!(
+            TotpCredentialRaw,
+            r#"
+            SELECT
+                totp_credentials.user_id,
+                totp_credentials.secret,
+                totp_credentials.created_at,
+                totp_credentials.confirmed_at,
+                totp_credentials.last_used_step,
+                (
+                    SELECT COUNT(*) FROM totp_recovery_codes
+                    WHERE totp_recovery_codes.user_id = totp_credentials.user_id
+                    AND totp_recovery_codes.used_at IS NULL
+                ) AS "recovery_codes_remaining!"
+            FROM
+                totp_credentials
+            WHERE
+                totp_credentials.user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn begin_enrollment(&self, user_id: i32, secret: &str) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO totp_credentials (user_id, secret, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = $2, created_at = $3, last_used_step = NULL
+            WHERE totp_credentials.confirmed_at IS NULL
+            "#,
+            user_id,
+            secret,
+            Utc::now().naive_utc()
+        )
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::Conflict(String::from(
+                "Two-factor authentication is already enabled",
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn confirm_enrollment(
+        &self,
+        user_id: i32,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE totp_credentials
+            SET confirmed_at = $2, last_used_step = $3
+            WHERE user_id = $1 AND confirmed_at IS NULL
+            "#,
+            user_id,
+            Utc::now().naive_utc(),
+            step
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::Conflict(String::from(
+                "Two-factor authentication is already enabled",
+            )));
+        }
+
+        sqlx::query!(
+            r#"
+            DELETE FROM totp_recovery_codes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO totp_recovery_codes (user_id, code_hash)
+            SELECT $1, code_hash FROM UNNEST($2::VARCHAR[]) AS code_hash
+            "#,
+            user_id,
+            recovery_code_hashes
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        record_audit(
+            &mut transaction,
+            user_id,
+            AuditAction::UserTotpEnable,
+            user_id,
+            json!({}),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn use_step(&self, user_id: i32, step: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE totp_credentials
+            SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_recovery_code(&self, user_id: i32, code_hash: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE totp_recovery_codes
+            SET used_at = $3
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+            user_id,
+            code_hash,
+            Utc::now().naive_utc()
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn disable_totp(&self, user_id: i32, actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM totp_credentials
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::Conflict(String::from(
+                "Two-factor authentication isn't enabled",
+            )));
+        }
+
+        sqlx::query!(
+            r#"
+            DELETE FROM totp_recovery_codes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserTotpDisable,
+            user_id,
+            json!({}),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn create_login_challenge(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> Result<()> {
+        let now = Utc::now().naive_utc();
+
+        let mut transaction = self.pool.begin().await?;
+
+        // 期限切れのものはここでまとめて消す。
+        sqlx::query!(
+            r#"
+            DELETE FROM login_challenges
+            WHERE expires_at <= $1
+            "#,
+            now
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO login_challenges (user_id, token_hash, created_at, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            user_id,
+            token_hash,
+            now,
+            expires_at
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn consume_login_challenge(&self, token_hash: &str) -> Result<Option<i32>> {
+        let user_id = sqlx::query!(
+            r#"
+            DELETE FROM login_challenges
+            WHERE token_hash = $1 AND expires_at > $2
+            RETURNING user_id
+            "#,
+            token_hash,
+            Utc::now().naive_utc()
+        )
+        .fetch_optional(self.pool)
+        .await?
+        .map(|r| r.user_id);
+
+        Ok(user_id)
+    }
+}
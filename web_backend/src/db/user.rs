@@ -1,8 +1,45 @@
-use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::Postgres;
 
-use crate::util::timezone::into_jst;
+use crate::db::audit::{record_audit, AuditAction};
+use crate::error::{AppError, Result};
+use crate::session::session_expires_at;
+use crate::util::timezone::into_timezone;
+
+/// Who can read the gongzuos of a user. Admins can read everything regardless.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Only the user.
+    Private,
+    /// Users who share a project or a team with the user.
+    #[default]
+    Team,
+    /// Every user.
+    Public,
+}
+
+impl Visibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Private => "private",
+            Visibility::Team => "team",
+            Visibility::Public => "public",
+        }
+    }
+
+    pub fn parse(visibility: &str) -> Option<Self> {
+        match visibility {
+            "private" => Some(Visibility::Private),
+            "team" => Some(Visibility::Team),
+            "public" => Some(Visibility::Public),
+            _ => None,
+        }
+    }
+}
 
 #[derive(sqlx::FromRow, Deserialize, Debug)]
 pub struct UserRaw {
@@ -11,9 +48,19 @@ pub struct UserRaw {
     pub password: String,
     pub salt: String,
     pub created_at: NaiveDateTime,
-    #[sqlx(default)]
-    pub session_token: Option<String>,
     pub is_admin: bool,
+    pub time_zone: String,
+    pub visibility: String,
+    /// `None` while the user is active.
+    pub deactivated_at: Option<NaiveDateTime>,
+    pub password_reset_required: bool,
+}
+
+impl UserRaw {
+    /// Unknown values are treated as the most restrictive one.
+    pub fn visibility(&self) -> Visibility {
+        Visibility::parse(&self.visibility).unwrap_or(Visibility::Private)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -21,22 +68,202 @@ pub struct User {
     pub id: i32,
     pub username: String,
     pub created_at: DateTime<FixedOffset>,
+    pub time_zone: String,
+    pub visibility: String,
 }
 
-impl From<UserRaw> for User {
-    fn from(value: UserRaw) -> Self {
+impl User {
+    /// Timestamps are shown in `tz`, which is usually the viewer's time zone.
+    pub fn from_raw(value: UserRaw, tz: Tz) -> Self {
         let UserRaw {
             id,
             username,
             created_at,
+            time_zone,
+            visibility,
             ..
         } = value;
 
-        let created_at = into_jst(created_at);
+        let created_at = into_timezone(created_at, tz);
         User {
             id,
             username,
             created_at,
+            time_zone,
+            visibility,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UserStatus {
+    Active,
+    /// Can't log in until reactivated.
+    Deactivated,
+}
+
+impl UserStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UserStatus::Active => "active",
+            UserStatus::Deactivated => "deactivated",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserFilter {
+    pub include_admins: bool,
+    /// `None` means users of any status.
+    pub status: Option<UserStatus>,
+}
+
+/// A user in listings, without the credentials.
+#[derive(sqlx::FromRow, Debug)]
+pub struct UserSummaryRaw {
+    pub id: i32,
+    pub username: String,
+    pub created_at: NaiveDateTime,
+    pub is_admin: bool,
+    pub time_zone: String,
+    pub visibility: String,
+    pub deactivated_at: Option<NaiveDateTime>,
+    pub password_reset_required: bool,
+    /// Of the most recently used session.
+    pub last_seen_at: Option<NaiveDateTime>,
+    /// Set while the username is locked after too many failed logins.
+    pub locked_until: Option<NaiveDateTime>,
+    pub two_factor_enabled: bool,
+    /// Names of the roles assigned to the user.
+    pub roles: Vec<String>,
+}
+
+impl UserSummaryRaw {
+    pub fn visibility(&self) -> Visibility {
+        Visibility::parse(&self.visibility).unwrap_or(Visibility::Private)
+    }
+}
+
+impl User {
+    pub fn from_summary(value: UserSummaryRaw, tz: Tz) -> Self {
+        let UserSummaryRaw {
+            id,
+            username,
+            created_at,
+            time_zone,
+            visibility,
+            ..
+        } = value;
+
+        User {
+            id,
+            username,
+            created_at: into_timezone(created_at, tz),
+            time_zone,
+            visibility,
+        }
+    }
+}
+
+/// A user as admins see it.
+#[derive(Serialize, Debug)]
+pub struct ManagedUser {
+    pub id: i32,
+    pub username: String,
+    pub created_at: DateTime<FixedOffset>,
+    pub is_admin: bool,
+    pub status: UserStatus,
+    pub deactivated_at: Option<DateTime<FixedOffset>>,
+    pub password_reset_required: bool,
+    pub last_seen_at: Option<DateTime<FixedOffset>>,
+    pub locked_until: Option<DateTime<FixedOffset>>,
+    pub two_factor_enabled: bool,
+    pub roles: Vec<String>,
+    pub time_zone: String,
+    pub visibility: String,
+}
+
+impl ManagedUser {
+    pub fn from_raw(value: UserSummaryRaw, tz: Tz) -> Self {
+        let UserSummaryRaw {
+            id,
+            username,
+            created_at,
+            is_admin,
+            time_zone,
+            visibility,
+            deactivated_at,
+            password_reset_required,
+            last_seen_at,
+            locked_until,
+            two_factor_enabled,
+            roles,
+        } = value;
+
+        ManagedUser {
+            id,
+            username,
+            created_at: into_timezone(created_at, tz),
+            is_admin,
+            status: if deactivated_at.is_some() {
+                UserStatus::Deactivated
+            } else {
+                UserStatus::Active
+            },
+            deactivated_at: deactivated_at.map(|deactivated_at| into_timezone(deactivated_at, tz)),
+            password_reset_required,
+            last_seen_at: last_seen_at.map(|last_seen_at| into_timezone(last_seen_at, tz)),
+            locked_until: locked_until.map(|locked_until| into_timezone(locked_until, tz)),
+            two_factor_enabled,
+            roles,
+            time_zone,
+            visibility,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Deserialize, Debug)]
+pub struct SessionRaw {
+    pub id: i32,
+    pub token: String,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub id: i32,
+    pub created_at: DateTime<FixedOffset>,
+    pub last_seen_at: DateTime<FixedOffset>,
+    pub expires_at: DateTime<FixedOffset>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl Session {
+    pub fn from_raw(value: SessionRaw, tz: Tz) -> Self {
+        let SessionRaw {
+            id,
+            created_at,
+            last_seen_at,
+            expires_at,
+            user_agent,
+            ip,
+            ..
+        } = value;
+
+        Session {
+            id,
+            created_at: into_timezone(created_at, tz),
+            last_seen_at: into_timezone(last_seen_at, tz),
+            expires_at: into_timezone(expires_at, tz),
+            user_agent,
+            ip,
         }
     }
 }
@@ -54,23 +281,79 @@ impl<'a> UserHandler<'a> {
 
 #[axum::async_trait]
 pub trait UserHandlerTrait {
-    async fn get_user_by_username(&self, username: &str) -> anyhow::Result<Option<UserRaw>>;
-    async fn users(&self) -> anyhow::Result<Vec<UserRaw>>;
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<UserRaw>>;
+    async fn get_user_by_id(&self, user_id: i32) -> Result<Option<UserRaw>>;
+    /// Users sorted by id.
+    async fn users(&self, filter: UserFilter) -> Result<Vec<UserSummaryRaw>>;
+    async fn user_summary(&self, user_id: i32) -> Result<Option<UserSummaryRaw>>;
+    /// `actor_id` is the user who registers the new user.
     async fn register_user(
         &self,
         username: &str,
-        hashed_password: &str,
-        salt: &str,
-    ) -> anyhow::Result<UserRaw>;
-    async fn update_session_token(&self, user_id: i32, session_token: &str) -> anyhow::Result<()>;
-    async fn ensure_session_token(&self, session_token: &str) -> anyhow::Result<Option<UserRaw>>;
-    async fn remove_session_token(&self, user_id: i32) -> anyhow::Result<()>;
-    async fn ensure_admin_user_is_registered(&self, username: &str) -> anyhow::Result<bool>;
+        password_hash: &str,
+        time_zone: &str,
+        actor_id: i32,
+    ) -> Result<UserRaw>;
+    /// Also revokes every session and access token of the user.
+    async fn deactivate_user(&self, user_id: i32, actor_id: i32) -> Result<()>;
+    async fn reactivate_user(&self, user_id: i32, actor_id: i32) -> Result<()>;
+    /// Sets a password chosen by an admin, which the user has to change.
+    /// Also revokes every session and access token of the user.
+    async fn reset_password(&self, user_id: i32, password_hash: &str, actor_id: i32) -> Result<()>;
+    /// Replaces the stored hash of the same password, e.g. with a stronger one.
+    async fn update_password_hash(&self, user_id: i32, password_hash: &str) -> Result<()>;
+    /// Changes the password of the user, who knows the current one,
+    /// and revokes every access token and every session except `session_token`.
+    async fn change_password(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        session_token: &str,
+    ) -> Result<()>;
+    /// Stores a reset token issued by `actor_id`. Unused tokens issued before are discarded.
+    async fn create_reset_token(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+        actor_id: i32,
+    ) -> Result<()>;
+    /// Uses up the reset token, sets the password and revokes every session
+    /// and access token of the user.
+    /// Returns the user id, or `None` if the token is unknown, used, expired
+    /// or belongs to a deactivated user.
+    async fn reset_password_with_token(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<Option<i32>>;
+    async fn rename_user(&self, user_id: i32, username: &str, actor_id: i32) -> Result<()>;
+    async fn update_admin(&self, user_id: i32, is_admin: bool, actor_id: i32) -> Result<()>;
+    async fn update_time_zone(&self, user_id: i32, time_zone: &str) -> Result<()>;
+    async fn update_visibility(&self, user_id: i32, visibility: Visibility) -> Result<()>;
+    /// Other users who share at least one project or team with the user.
+    async fn teammate_ids(&self, user_id: i32) -> Result<Vec<i32>>;
+    async fn create_session(
+        &self,
+        user_id: i32,
+        session_token: &str,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<()>;
+    /// Returns the owner of `session_token` and slides the session's expiry forward.
+    /// Expired or revoked tokens, and tokens of deactivated users, yield `None`.
+    async fn ensure_session_token(&self, session_token: &str) -> Result<Option<UserRaw>>;
+    async fn sessions_by_user_id(&self, user_id: i32) -> Result<Vec<SessionRaw>>;
+    async fn remove_session_token(&self, session_token: &str) -> Result<()>;
+    /// Returns `false` if the session does not exist or belongs to another user.
+    async fn revoke_session(&self, user_id: i32, session_id: i32) -> Result<bool>;
+    async fn revoke_all_sessions(&self, user_id: i32) -> Result<()>;
+    async fn ensure_admin_user_is_registered(&self, username: &str) -> Result<bool>;
 }
 
 #[axum::async_trait]
 impl UserHandlerTrait for UserHandler<'_> {
-    async fn get_user_by_username(&self, username: &str) -> anyhow::Result<Option<UserRaw>> {
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<UserRaw>> {
         let user = sqlx::query_as!(
             UserRaw,
             r#"
@@ -85,85 +368,850 @@ impl UserHandlerTrait for UserHandler<'_> {
         Ok(user)
     }
 
-    async fn users(&self) -> anyhow::Result<Vec<UserRaw>> {
-        let users = sqlx::query_as!(UserRaw, "SELECT * FROM users")
-            .fetch_all(self.pool)
-            .await?;
+    async fn get_user_by_id(&self, user_id: i32) -> Result<Option<UserRaw>> {
+        let user = sqlx::query_as!(
+            UserRaw,
+            r#"
+            SELECT * FROM users
+            WHERE id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn users(&self, filter: UserFilter) -> Result<Vec<UserSummaryRaw>> {
+        let UserFilter {
+            include_admins,
+            status,
+        } = filter;
+
+        let users = sqlx::query_as!(
+            UserSummaryRaw,
+            r#"
+            SELECT
+                users.id,
+                users.username,
+                users.created_at,
+                users.is_admin,
+                users.time_zone,
+                users.visibility,
+                users.deactivated_at,
+                users.password_reset_required,
+                (
+                    SELECT MAX(sessions.last_seen_at) FROM sessions
+                    WHERE sessions.user_id = users.id
+                ) AS last_seen_at,
+                (
+                    SELECT login_failures.locked_until FROM login_failures
+                    WHERE login_failures.scope = 'username'
+                    AND login_failures.key = users.username
+                    AND login_failures.locked_until > NOW() AT TIME ZONE 'UTC'
+                ) AS locked_until,
+                EXISTS (
+                    SELECT 1 FROM totp_credentials
+                    WHERE totp_credentials.user_id = users.id
+                    AND totp_credentials.confirmed_at IS NOT NULL
+                ) AS "two_factor_enabled!",
+                ARRAY(
+                    SELECT roles.name FROM user_roles
+                    JOIN roles ON user_roles.role_id = roles.id
+                    WHERE user_roles.user_id = users.id
+                    ORDER BY roles.name
+                ) AS "roles!"
+            FROM
+                users
+            WHERE
+                ($1 OR users.is_admin = false)
+            AND
+                (
+                    $2::VARCHAR IS NULL
+                OR
+                    ($2 = 'active' AND users.deactivated_at IS NULL)
+                OR
+                    ($2 = 'deactivated' AND users.deactivated_at IS NOT NULL)
+                )
+            ORDER BY
+                users.id
+            "#,
+            include_admins,
+            status.map(|status| status.as_str())
+        )
+        .fetch_all(self.pool)
+        .await?;
 
         Ok(users)
     }
 
+    async fn user_summary(&self, user_id: i32) -> Result<Option<UserSummaryRaw>> {
+        let user = sqlx::query_as!(
+            UserSummaryRaw,
+            r#"
+            SELECT
+                users.id,
+                users.username,
+                users.created_at,
+                users.is_admin,
+                users.time_zone,
+                users.visibility,
+                users.deactivated_at,
+                users.password_reset_required,
+                (
+                    SELECT MAX(sessions.last_seen_at) FROM sessions
+                    WHERE sessions.user_id = users.id
+                ) AS last_seen_at,
+                (
+                    SELECT login_failures.locked_until FROM login_failures
+                    WHERE login_failures.scope = 'username'
+                    AND login_failures.key = users.username
+                    AND login_failures.locked_until > NOW() AT TIME ZONE 'UTC'
+                ) AS locked_until,
+                EXISTS (
+                    SELECT 1 FROM totp_credentials
+                    WHERE totp_credentials.user_id = users.id
+                    AND totp_credentials.confirmed_at IS NOT NULL
+                ) AS "two_factor_enabled!",
+                ARRAY(
+                    SELECT roles.name FROM user_roles
+                    JOIN roles ON user_roles.role_id = roles.id
+                    WHERE user_roles.user_id = users.id
+                    ORDER BY roles.name
+                ) AS "roles!"
+            FROM
+                users
+            WHERE
+                users.id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
     async fn register_user(
         &self,
         username: &str,
-        hashed_password: &str,
-        salt: &str,
-    ) -> anyhow::Result<UserRaw> {
+        password_hash: &str,
+        time_zone: &str,
+        actor_id: i32,
+    ) -> Result<UserRaw> {
+        let mut transaction = self.pool.begin().await?;
+
         let user = sqlx::query_as!(
             UserRaw,
             r#"
-            INSERT INTO users (username, password, salt)
+            INSERT INTO users (username, password, time_zone)
             VALUES ($1, $2, $3)
             RETURNING
             *
             "#,
             username,
-            hashed_password,
-            salt
+            password_hash,
+            time_zone
         )
-        .fetch_one(self.pool)
+        .fetch_one(&mut *transaction)
         .await?;
 
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserCreate,
+            user.id,
+            json!({ "username": user.username }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
         Ok(user)
     }
 
-    async fn update_session_token(&self, user_id: i32, session_token: &str) -> anyhow::Result<()> {
+    async fn deactivate_user(&self, user_id: i32, actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let deactivated_at = sqlx::query!(
+            r#"
+            SELECT deactivated_at FROM users
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            user_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", user_id)))?
+        .deactivated_at;
+
+        if deactivated_at.is_some() {
+            return Err(AppError::Conflict(format!(
+                "User {} is already deactivated",
+                user_id
+            )));
+        }
+
         sqlx::query!(
             r#"
             UPDATE users
-            SET session_token = $1
-            WHERE id = $2
+            SET deactivated_at = $2
+            WHERE id = $1
+            "#,
+            user_id,
+            Utc::now().naive_utc()
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = $1
             "#,
-            session_token,
             user_id
         )
-        .execute(self.pool)
+        .execute(&mut *transaction)
+        .await?;
+
+        // 有効に戻しても、古いトークンは使えないままにする。
+        let access_tokens_revoked = sqlx::query!(
+            r#"
+            DELETE FROM access_tokens
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserDeactivate,
+            user_id,
+            json!({ "access_tokens_revoked": access_tokens_revoked }),
+        )
         .await?;
 
+        transaction.commit().await?;
+
         Ok(())
     }
 
-    async fn remove_session_token(&self, user_id: i32) -> anyhow::Result<()> {
+    async fn reactivate_user(&self, user_id: i32, actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let deactivated_at = sqlx::query!(
+            r#"
+            SELECT deactivated_at FROM users
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            user_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", user_id)))?
+        .deactivated_at;
+
+        if deactivated_at.is_none() {
+            return Err(AppError::Conflict(format!(
+                "User {} is already active",
+                user_id
+            )));
+        }
+
         sqlx::query!(
             r#"
             UPDATE users
-            SET session_token = NULL
+            SET deactivated_at = NULL
             WHERE id = $1
             "#,
             user_id
         )
+        .execute(&mut *transaction)
+        .await?;
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserReactivate,
+            user_id,
+            json!({}),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn reset_password(&self, user_id: i32, password_hash: &str, actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET password = $2, salt = '', password_reset_required = true
+            WHERE id = $1
+            "#,
+            user_id,
+            password_hash
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("User {} not found", user_id)));
+        }
+
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let access_tokens_revoked = sqlx::query!(
+            r#"
+            DELETE FROM access_tokens
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        // パスワードそのものは記録しない。
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserPasswordReset,
+            user_id,
+            json!({ "access_tokens_revoked": access_tokens_revoked }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_password_hash(&self, user_id: i32, password_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password = $2, salt = ''
+            WHERE id = $1
+            "#,
+            user_id,
+            password_hash
+        )
         .execute(self.pool)
         .await?;
 
         Ok(())
     }
 
-    async fn ensure_session_token(&self, session_token: &str) -> anyhow::Result<Option<UserRaw>> {
-        let user = sqlx::query_as!(
-            UserRaw,
+    async fn change_password(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        session_token: &str,
+    ) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
             r#"
-            SELECT * FROM users
-            WHERE session_token = $1
+            UPDATE users
+            SET password = $2, salt = '', password_reset_required = false
+            WHERE id = $1
             "#,
+            user_id,
+            password_hash
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = $1 AND token <> $2
+            "#,
+            user_id,
             session_token
         )
-        .fetch_optional(self.pool)
+        .execute(&mut *transaction)
+        .await?;
+
+        let access_tokens_revoked = sqlx::query!(
+            r#"
+            DELETE FROM access_tokens
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        record_audit(
+            &mut transaction,
+            user_id,
+            AuditAction::UserPasswordChange,
+            user_id,
+            json!({
+                "via": "current_password",
+                "access_tokens_revoked": access_tokens_revoked,
+            }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn create_reset_token(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+        actor_id: i32,
+    ) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM password_reset_tokens
+            WHERE user_id = $1 AND used_at IS NULL
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO password_reset_tokens (user_id, token_hash, created_by, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            user_id,
+            token_hash,
+            actor_id,
+            Utc::now().naive_utc(),
+            expires_at
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            if let sqlx::Error::Database(database_error) = &e {
+                // foreign_key_violation
+                if database_error.code().as_deref() == Some("23503") {
+                    return AppError::NotFound(format!("User {} not found", user_id));
+                }
+            }
+            e.into()
+        })?;
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserResetTokenIssue,
+            user_id,
+            json!({ "expires_at": expires_at }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn reset_password_with_token(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<Option<i32>> {
+        let now = Utc::now().naive_utc();
+
+        let mut transaction = self.pool.begin().await?;
+
+        // 使用済みにできたときだけ続ける。同じトークンを同時に使われても一度しか通らない。
+        let Some(user_id) = sqlx::query!(
+            r#"
+            UPDATE password_reset_tokens
+            SET used_at = $2
+            FROM users
+            WHERE
+                password_reset_tokens.user_id = users.id
+            AND
+                password_reset_tokens.token_hash = $1
+            AND
+                password_reset_tokens.used_at IS NULL
+            AND
+                password_reset_tokens.expires_at > $2
+            AND
+                users.deactivated_at IS NULL
+            RETURNING
+                password_reset_tokens.user_id
+            "#,
+            token_hash,
+            now
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .map(|r| r.user_id) else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password = $2, salt = '', password_reset_required = false
+            WHERE id = $1
+            "#,
+            user_id,
+            password_hash
+        )
+        .execute(&mut *transaction)
         .await?;
 
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let access_tokens_revoked = sqlx::query!(
+            r#"
+            DELETE FROM access_tokens
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        record_audit(
+            &mut transaction,
+            user_id,
+            AuditAction::UserPasswordChange,
+            user_id,
+            json!({
+                "via": "reset_token",
+                "access_tokens_revoked": access_tokens_revoked,
+            }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(Some(user_id))
+    }
+
+    async fn rename_user(&self, user_id: i32, username: &str, actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let before = sqlx::query!(
+            r#"
+            SELECT username FROM users
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            user_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", user_id)))?
+        .username;
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET username = $2
+            WHERE id = $1
+            "#,
+            user_id,
+            username
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            if let sqlx::Error::Database(database_error) = &e {
+                // unique_violation
+                if database_error.code().as_deref() == Some("23505") {
+                    return AppError::Conflict(format!("User {} already exists", username));
+                }
+            }
+            e.into()
+        })?;
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserRename,
+            user_id,
+            json!({ "before": before, "after": username }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_admin(&self, user_id: i32, is_admin: bool, actor_id: i32) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET is_admin = $2
+            WHERE id = $1
+            "#,
+            user_id,
+            is_admin
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("User {} not found", user_id)));
+        }
+
+        record_audit(
+            &mut transaction,
+            actor_id,
+            AuditAction::UserAdminUpdate,
+            user_id,
+            json!({ "is_admin": is_admin }),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_time_zone(&self, user_id: i32, time_zone: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET time_zone = $1
+            WHERE id = $2
+            "#,
+            time_zone,
+            user_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_visibility(&self, user_id: i32, visibility: Visibility) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET visibility = $1
+            WHERE id = $2
+            "#,
+            visibility.as_str(),
+            user_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn teammate_ids(&self, user_id: i32) -> Result<Vec<i32>> {
+        let teammate_ids = sqlx::query!(
+            r#"
+            SELECT
+                others.user_id AS "user_id!"
+            FROM
+                project_members AS mine
+            JOIN
+                project_members AS others
+            ON
+                mine.project_id = others.project_id
+            WHERE
+                mine.user_id = $1
+            AND
+                others.user_id != $1
+            UNION
+            SELECT
+                others.user_id
+            FROM
+                team_memberships AS mine
+            JOIN
+                team_memberships AS others
+            ON
+                mine.team_id = others.team_id
+            WHERE
+                mine.user_id = $1
+            AND
+                others.user_id != $1
+            "#,
+            user_id
+        )
+        .fetch_all(self.pool)
+        .await?
+        .into_iter()
+        .map(|r| r.user_id)
+        .collect();
+
+        Ok(teammate_ids)
+    }
+
+    async fn create_session(
+        &self,
+        user_id: i32,
+        session_token: &str,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<()> {
+        let now = Utc::now().naive_utc();
+
+        let mut transaction = self.pool.begin().await?;
+
+        // 失効済みのsessionはここで掃除しておく。
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = $1 AND expires_at <= $2
+            "#,
+            user_id,
+            now
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (token, user_id, created_at, last_seen_at, expires_at, user_agent, ip)
+            VALUES ($1, $2, $3, $3, $4, $5, $6)
+            "#,
+            session_token,
+            user_id,
+            now,
+            session_expires_at(now),
+            user_agent,
+            ip
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn ensure_session_token(&self, session_token: &str) -> Result<Option<UserRaw>> {
+        let now = Utc::now().naive_utc();
+
+        let Some(session) = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET last_seen_at = $2, expires_at = $3
+            WHERE token = $1 AND expires_at > $2
+            RETURNING user_id
+            "#,
+            session_token,
+            now,
+            session_expires_at(now)
+        )
+        .fetch_optional(self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        // 無効化したときにsessionは消しているが、念のため。
+        let user = self
+            .get_user_by_id(session.user_id)
+            .await?
+            .filter(|user| user.deactivated_at.is_none());
+
         Ok(user)
     }
 
-    async fn ensure_admin_user_is_registered(&self, username: &str) -> anyhow::Result<bool> {
+    async fn sessions_by_user_id(&self, user_id: i32) -> Result<Vec<SessionRaw>> {
+        let sessions = sqlx::query_as!(
+            SessionRaw,
+            r#"
+            SELECT * FROM sessions
+            WHERE user_id = $1 AND expires_at > $2
+            ORDER BY last_seen_at DESC
+            "#,
+            user_id,
+            Utc::now().naive_utc()
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn remove_session_token(&self, session_token: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE token = $1
+            "#,
+            session_token
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_session(&self, user_id: i32, session_id: i32) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE id = $1 AND user_id = $2
+            "#,
+            session_id,
+            user_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn revoke_all_sessions(&self, user_id: i32) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn ensure_admin_user_is_registered(&self, username: &str) -> Result<bool> {
         let user = sqlx::query_as!(
             UserRaw,
             r#"
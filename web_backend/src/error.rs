@@ -1,26 +1,110 @@
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use once_cell::sync::Lazy;
 use serde_json::json;
 
 pub type Result<T> = std::result::Result<T, AppError>;
 
-pub struct AppError {
-    inner: anyhow::Error,
+/// Whether details of internal errors are sent to clients.
+/// They are hidden unless `APP_DEBUG_ERRORS=1`; enable it only in development.
+static EXPOSE_INTERNAL_ERRORS: Lazy<bool> = Lazy::new(|| {
+    std::env::var("APP_DEBUG_ERRORS").is_ok_and(|value| value == "1" || value == "true")
+});
+
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Conflict(String),
+    /// The gongzuo overlaps with another gongzuo of the same user.
+    Overlap(String),
+    Unauthorized(String),
+    Forbidden(String),
+    /// An admin reset the password; the user has to change it first.
+    PasswordResetRequired(String),
+    Validation(String),
+    /// Too many failed attempts. The client can retry after `retry_after_seconds`.
+    TooManyRequests {
+        message: String,
+        retry_after_seconds: i64,
+    },
+    Internal(anyhow::Error),
+}
+
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) | AppError::Overlap(_) => StatusCode::CONFLICT,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) | AppError::PasswordResetRequired(_) => StatusCode::FORBIDDEN,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable, machine-readable error code which clients can switch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Overlap(_) => "overlap",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::PasswordResetRequired(_) => "password_reset_required",
+            AppError::Validation(_) => "validation",
+            AppError::TooManyRequests { .. } => "too_many_requests",
+            AppError::Internal(_) => "internal",
+        }
+    }
 }
 
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        let status_code = self.status_code();
+        let code = self.code();
+        let retry_after = match &self {
+            AppError::TooManyRequests {
+                retry_after_seconds,
+                ..
+            } => Some(HeaderValue::from(*retry_after_seconds)),
+            _ => None,
+        };
+        let message = match self {
+            AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::Overlap(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::PasswordResetRequired(message)
+            | AppError::Validation(message)
+            | AppError::TooManyRequests { message, .. } => message,
+            AppError::Internal(inner) => {
+                eprintln!("Internal error: {:?}", inner);
+                if *EXPOSE_INTERNAL_ERRORS {
+                    format!("Something went wrong: {}", inner)
+                } else {
+                    String::from("Something went wrong")
+                }
+            }
+        };
+
+        let mut response = (
+            status_code,
             Json(json!({
-                        "message": format!("Something went wrong: {}", self.inner)
+                "code": code,
+                "message": message,
             })),
         )
-            .into_response()
+            .into_response();
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, retry_after);
+        }
+        response
     }
 }
 
@@ -29,8 +113,6 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(inner: E) -> Self {
-        Self {
-            inner: inner.into(),
-        }
+        AppError::Internal(inner.into())
     }
 }
@@ -1,5 +1,19 @@
+pub mod access_tokens;
+pub mod admin;
+pub mod categories;
+pub mod clients;
 pub mod gongzuo;
+pub mod invoices;
 pub mod login;
 pub mod logout;
+pub mod password_reset;
+pub mod projects;
+pub mod rates;
 pub mod register;
+pub mod reports;
+pub mod roles;
+pub mod sessions;
+pub mod tags;
+pub mod teams;
+pub mod totp;
 pub mod users;
@@ -0,0 +1,100 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::AuthUser;
+use crate::db::access_token::{AccessToken, AccessTokenHandlerTrait, Scope};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::password;
+use crate::util::timezone::TimeZoneQuery;
+
+/// Personal access tokens of the current user. The tokens themselves can't be seen again.
+pub async fn access_tokens(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let access_tokens = db
+        .access_token_handler()
+        .access_tokens(user.id)
+        .await?
+        .into_iter()
+        .map(|access_token| AccessToken::from_raw(access_token, tz))
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "access_tokens": access_tokens })),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccessTokenPayload {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// The token is shown only in this response.
+pub async fn create_access_token(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(tz): Query<TimeZoneQuery>,
+    Json(payload): Json<AccessTokenPayload>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation(String::from("name must not be empty")));
+    }
+    if payload.scopes.is_empty() {
+        return Err(AppError::Validation(String::from(
+            "scopes must not be empty",
+        )));
+    }
+
+    let (token, token_hash) = password::create_access_token()?;
+
+    let access_token = db
+        .access_token_handler()
+        .create_access_token(user.id, name, &token_hash, &payload.scopes)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "access_token": AccessToken::from_raw(access_token, tz),
+            "token": token,
+        })),
+    ))
+}
+
+pub async fn revoke_access_token(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(access_token_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    if !db
+        .access_token_handler()
+        .revoke_access_token(user.id, access_token_id)
+        .await?
+    {
+        return Err(AppError::NotFound(format!(
+            "Access token {} not found",
+            access_token_id
+        )));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Access token revoked"
+        })),
+    ))
+}
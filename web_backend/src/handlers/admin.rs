@@ -0,0 +1,281 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::Utc;
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::AdminUser;
+use crate::db::audit::{AuditHandlerTrait, AuditLog};
+use crate::db::login_failure::LoginFailureHandlerTrait;
+use crate::db::totp::TotpHandlerTrait;
+use crate::db::user::{ManagedUser, UserFilter, UserHandlerTrait, UserStatus};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::password;
+use crate::util::timezone::{into_timezone, TimeZoneQuery};
+
+const DEFAULT_AUDIT_LOG_LIMIT: i64 = 100;
+const MAX_AUDIT_LOG_LIMIT: i64 = 1000;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ManagedUserListQuery {
+    pub status: Option<UserStatus>,
+}
+
+/// Every user including admins, with their status.
+pub async fn users(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Query(query): Query<ManagedUserListQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let users = db
+        .user_handler()
+        .users(UserFilter {
+            include_admins: true,
+            status: query.status,
+        })
+        .await?
+        .into_iter()
+        .map(|user| ManagedUser::from_raw(user, tz))
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!({ "users": users }))))
+}
+
+async fn managed_user(db: &DB, user_id: i32, tz: Tz) -> Result<ManagedUser> {
+    db.user_handler()
+        .user_summary(user_id)
+        .await?
+        .map(|user| ManagedUser::from_raw(user, tz))
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", user_id)))
+}
+
+/// Admins can't lock themselves out.
+fn ensure_not_self(admin_id: i32, user_id: i32, message: &str) -> Result<()> {
+    if admin_id == user_id {
+        return Err(AppError::Conflict(message.to_string()));
+    }
+    Ok(())
+}
+
+/// The user can't log in, and their sessions are revoked.
+pub async fn deactivate_user(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+    ensure_not_self(user.id, user_id, "Admins can't deactivate themselves")?;
+
+    db.user_handler().deactivate_user(user_id, user.id).await?;
+
+    let user = managed_user(&db, user_id, tz).await?;
+    Ok((StatusCode::OK, Json(json!({ "user": user }))))
+}
+
+pub async fn reactivate_user(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    db.user_handler().reactivate_user(user_id, user.id).await?;
+
+    let user = managed_user(&db, user_id, tz).await?;
+    Ok((StatusCode::OK, Json(json!({ "user": user }))))
+}
+
+/// Lets the user log in again after too many failed logins.
+/// Lockouts of IP addresses stay until they end.
+pub async fn unlock_user(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    db.login_failure_handler()
+        .unlock_user(user_id, user.id)
+        .await?;
+
+    let user = managed_user(&db, user_id, tz).await?;
+    Ok((StatusCode::OK, Json(json!({ "user": user }))))
+}
+
+/// Turns two-factor authentication off for a user who lost the authenticator and the recovery codes.
+/// The user can enroll again after logging in with the password.
+pub async fn reset_totp(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+    managed_user(&db, user_id, tz).await?;
+
+    db.totp_handler().disable_totp(user_id, user.id).await?;
+
+    let user = managed_user(&db, user_id, tz).await?;
+    Ok((StatusCode::OK, Json(json!({ "user": user }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PasswordResetPayload {
+    /// Temporary password which the admin passes on to the user.
+    pub password: String,
+}
+
+/// Sets a temporary password. The user's sessions and access tokens are revoked,
+/// and the user has to change the password after logging in.
+pub async fn reset_password(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Json(payload): Json<PasswordResetPayload>,
+) -> Result<impl IntoResponse> {
+    if payload.password.is_empty() {
+        return Err(AppError::Validation(String::from(
+            "password must not be empty",
+        )));
+    }
+
+    let password_hash = password::hash(&payload.password)?;
+
+    db.user_handler()
+        .reset_password(user_id, &password_hash, user.id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Password reset",
+        })),
+    ))
+}
+
+/// Issues a one-time token with which the user can set a new password at `/password_reset`.
+/// The token is shown only in this response.
+pub async fn issue_reset_token(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let (token, token_hash) = password::create_token()?;
+    let expires_at = Utc::now().naive_utc() + password::reset_token_ttl();
+
+    db.user_handler()
+        .create_reset_token(user_id, &token_hash, expires_at, user.id)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "token": token,
+            "expires_at": into_timezone(expires_at, tz),
+        })),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RenamePayload {
+    pub username: String,
+}
+
+pub async fn rename_user(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+    Json(payload): Json<RenamePayload>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let username = payload.username.trim();
+    if username.is_empty() {
+        return Err(AppError::Validation(String::from(
+            "username must not be empty",
+        )));
+    }
+
+    db.user_handler()
+        .rename_user(user_id, username, user.id)
+        .await?;
+
+    let user = managed_user(&db, user_id, tz).await?;
+    Ok((StatusCode::OK, Json(json!({ "user": user }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AdminPayload {
+    pub is_admin: bool,
+}
+
+/// Promotes the user to admin or demotes them.
+pub async fn update_admin(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+    Json(payload): Json<AdminPayload>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+    if !payload.is_admin {
+        ensure_not_self(user.id, user_id, "Admins can't demote themselves")?;
+    }
+
+    db.user_handler()
+        .update_admin(user_id, payload.is_admin, user.id)
+        .await?;
+
+    let user = managed_user(&db, user_id, tz).await?;
+    Ok((StatusCode::OK, Json(json!({ "user": user }))))
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AuditLogQuery {
+    pub target_user_id: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+/// Most recent first.
+pub async fn audit_logs(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Query(query): Query<AuditLogQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LOG_LIMIT);
+    if !(1..=MAX_AUDIT_LOG_LIMIT).contains(&limit) {
+        return Err(AppError::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_AUDIT_LOG_LIMIT
+        )));
+    }
+
+    let audit_logs = db
+        .audit_handler()
+        .audit_logs(query.target_user_id, limit)
+        .await?
+        .into_iter()
+        .map(|audit_log| AuditLog::from_raw(audit_log, tz))
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!({ "audit_logs": audit_logs }))))
+}
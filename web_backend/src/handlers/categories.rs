@@ -0,0 +1,117 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::{AdminUser, AuthUser};
+use crate::db::category::{CategoryHandlerTrait, CategoryPayload};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CategoryListQuery {
+    pub include_archived: bool,
+}
+
+pub async fn categories(
+    State(db): State<DB>,
+    _: AuthUser,
+    Query(query): Query<CategoryListQuery>,
+) -> Result<impl IntoResponse> {
+    let categories = db
+        .category_handler()
+        .categories(query.include_archived)
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "categories": categories }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CategoryRequest {
+    pub name: String,
+    /// `#rrggbb`
+    pub color: String,
+    pub counts_as_work: bool,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl CategoryRequest {
+    fn validate(self) -> Result<CategoryPayload> {
+        let CategoryRequest {
+            name,
+            color,
+            counts_as_work,
+            archived,
+        } = self;
+
+        let name = name.trim().to_string();
+        if name.is_empty() || name.chars().count() > 64 {
+            return Err(AppError::Validation(String::from(
+                "name must be between 1 and 64 characters",
+            )));
+        }
+
+        let is_hex_color = color.len() == 7
+            && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if !is_hex_color {
+            return Err(AppError::Validation(format!(
+                "color must be in the form of #rrggbb: {}",
+                color
+            )));
+        }
+
+        Ok(CategoryPayload {
+            name,
+            color: color.to_lowercase(),
+            counts_as_work,
+            archived,
+        })
+    }
+}
+
+pub async fn create_category(
+    State(db): State<DB>,
+    _: AdminUser,
+    Json(payload): Json<CategoryRequest>,
+) -> Result<impl IntoResponse> {
+    let category = db
+        .category_handler()
+        .create_category(payload.validate()?)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "category": category }))))
+}
+
+pub async fn update_category(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(category_id): Path<i32>,
+    Json(payload): Json<CategoryRequest>,
+) -> Result<impl IntoResponse> {
+    let category = db
+        .category_handler()
+        .update_category(category_id, payload.validate()?)
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "category": category }))))
+}
+
+pub async fn delete_category(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(category_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    db.category_handler().delete_category(category_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Category deleted"
+        })),
+    ))
+}
@@ -0,0 +1,86 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::{AdminUser, AuthUser};
+use crate::db::project::{ClientPayload, ProjectHandlerTrait};
+use crate::db::DB;
+use crate::error::Result;
+use crate::handlers::projects::validate_name;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ClientListQuery {
+    pub include_archived: bool,
+}
+
+pub async fn clients(
+    State(db): State<DB>,
+    _: AuthUser,
+    Query(query): Query<ClientListQuery>,
+) -> Result<impl IntoResponse> {
+    let clients = db.project_handler().clients(query.include_archived).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "clients": clients }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ClientRequest {
+    pub name: String,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl ClientRequest {
+    fn validate(self) -> Result<ClientPayload> {
+        Ok(ClientPayload {
+            name: validate_name(&self.name)?,
+            archived: self.archived,
+        })
+    }
+}
+
+pub async fn create_client(
+    State(db): State<DB>,
+    _: AdminUser,
+    Json(payload): Json<ClientRequest>,
+) -> Result<impl IntoResponse> {
+    let client = db
+        .project_handler()
+        .create_client(payload.validate()?)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "client": client }))))
+}
+
+pub async fn update_client(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(client_id): Path<i32>,
+    Json(payload): Json<ClientRequest>,
+) -> Result<impl IntoResponse> {
+    let client = db
+        .project_handler()
+        .update_client(client_id, payload.validate()?)
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "client": client }))))
+}
+
+pub async fn delete_client(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(client_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    db.project_handler().delete_client(client_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Client deleted"
+        })),
+    ))
+}
@@ -3,110 +3,195 @@ use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::db::gongzuo::{ContentKind, Gongzuo, GongzuoHandlerTrait, GongzuoPayload};
-use crate::db::user::UserHandlerTrait;
+use crate::access::{can_read, readable_users};
+use crate::auth::AuthUser;
+use crate::db::gongzuo::{
+    Gongzuo, GongzuoCursor, GongzuoFilter, GongzuoHandlerTrait, GongzuoPayload, GongzuoRaw,
+    SortOrder,
+};
+use crate::db::revision::{GongzuoRevision, RevisionHandlerTrait};
+use crate::db::tag::parse_tag_list;
 use crate::db::DB;
-use crate::error::Result;
+use crate::error::{AppError, Result};
+use crate::trash::trash_retention;
+use crate::util::timezone::TimeZoneQuery;
 use serde_with::NoneAsEmptyString;
 
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
 #[serde_with::serde_as]
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 #[serde(default)]
-pub struct SessionQuery {
+pub struct GongzuoListQuery {
+    pub user_id: Option<i32>,
+    pub started_after: Option<DateTime<Utc>>,
+    pub started_before: Option<DateTime<Utc>>,
+    pub ended_after: Option<DateTime<Utc>>,
+    pub ended_before: Option<DateTime<Utc>>,
+    #[serde(alias = "content_kind")]
+    pub category_id: Option<i32>,
+    pub project_id: Option<i32>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub content: Option<String>,
+    /// Comma-separated tags, e.g. `review,clientA`. Gongzuos must have all of them.
     #[serde_as(as = "NoneAsEmptyString")]
-    pub session_token: Option<String>,
+    pub tags: Option<String>,
+    pub ongoing: bool,
+    pub order: SortOrder,
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
 }
 
-#[allow(clippy::derivable_impls)]
-impl Default for SessionQuery {
-    fn default() -> Self {
-        Self {
-            session_token: None,
-        }
-    }
-}
+pub async fn all_ongzuos(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(query): Query<GongzuoListQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
 
-pub fn session_token_invalid_error() -> Result<(StatusCode, axum::Json<serde_json::Value>)> {
-    Ok((
-        StatusCode::UNAUTHORIZED,
-        Json(json!({
-            "message": "Invalid session token"
-        })),
-    ))
-}
+    let readable = readable_users(&db, &user).await?;
+    let page = gongzuo_page(&db, query, readable.user_ids(), tz).await?;
 
-#[macro_export]
-macro_rules! get_user_by_session_token {
-    ($db:expr, $session_token:expr) => {{
-        let Some(session_token) = $session_token else {
-                                            return session_token_invalid_error();
-                                        };
-
-        match $db
-            .user_handler()
-            .ensure_session_token(&session_token)
-            .await?
-        {
-            Some(user) => user,
-            None => return session_token_invalid_error(),
-        }
-    }};
+    Ok((StatusCode::OK, Json(page)))
 }
 
-pub async fn all_ongzuos(
-    State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
-) -> Result<impl IntoResponse> {
-    let _user = get_user_by_session_token!(db, session_token);
+/// Gongzuos matching `query`.
+/// They are paged as `{ gongzuos, next_cursor }` if `limit` or `cursor` is given.
+/// Otherwise all of them are returned as a bare array, as before paging was added.
+/// `user_ids` limits whose gongzuos are listed; `None` means everyone's.
+pub async fn gongzuo_page(
+    db: &DB,
+    query: GongzuoListQuery,
+    user_ids: Option<Vec<i32>>,
+    tz: Tz,
+) -> Result<serde_json::Value> {
+    let GongzuoListQuery {
+        user_id,
+        started_after,
+        started_before,
+        ended_after,
+        ended_before,
+        category_id,
+        project_id,
+        content,
+        tags,
+        ongoing,
+        order,
+        cursor,
+        limit,
+    } = query;
+
+    let paged = limit.is_some() || cursor.is_some();
+
+    let cursor = cursor
+        .map(|cursor| {
+            GongzuoCursor::decode(&cursor)
+                .ok_or_else(|| AppError::Validation(format!("Invalid cursor: {}", cursor)))
+        })
+        .transpose()?;
+
+    let tags = tags.as_deref().map(parse_tag_list).transpose()?;
+
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if paged && !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let filter = GongzuoFilter {
+        user_id,
+        user_ids,
+        started_after,
+        started_before,
+        ended_after,
+        ended_before,
+        category_id,
+        project_id,
+        content,
+        tags,
+        ongoing_only: ongoing,
+        order,
+        cursor,
+        // 次のページがあるかを知るために1件多く取得する。
+        limit: paged.then_some(limit + 1),
+    };
+
+    let mut gongzuos = db.gongzuo_handler().gongzuos(&filter).await?;
 
-    let gongzuos = db.gongzuo_handler().all_gongzuos().await?;
-    let gongzuos = gongzuos.into_iter().map(Gongzuo::from).collect::<Vec<_>>();
-    Ok((StatusCode::OK, Json(json!(gongzuos))))
+    let next_cursor = if gongzuos.len() as i64 > limit {
+        gongzuos.truncate(limit as usize);
+        gongzuos
+            .last()
+            .map(|gongzuo| GongzuoCursor::from(gongzuo).encode())
+    } else {
+        None
+    };
+
+    let gongzuos = gongzuos
+        .into_iter()
+        .map(|gongzuo| Gongzuo::from_raw(gongzuo, tz))
+        .collect::<Vec<_>>();
+
+    if !paged {
+        return Ok(json!(gongzuos));
+    }
+
+    Ok(json!({
+        "gongzuos": gongzuos,
+        "next_cursor": next_cursor,
+    }))
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GongzuoStartPayload {
-    pub content_kind: ContentKind,
+    /// `content_kind` is accepted for old clients. Its values are the ids of the seeded categories.
+    #[serde(alias = "content_kind")]
+    pub category_id: i32,
+    pub project_id: Option<i32>,
     pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 pub async fn start_gongzuo(
     State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
+    AuthUser { user, .. }: AuthUser,
     Json(payload): Json<GongzuoStartPayload>,
 ) -> Result<impl IntoResponse> {
-    let user = get_user_by_session_token!(db, session_token);
-
     let GongzuoStartPayload {
-        content_kind,
+        category_id,
+        project_id,
         content,
+        tags,
     } = payload;
 
     let started_at = Utc::now();
 
     let ongoing_gongzuo = db.gongzuo_handler().gongzuo_at(user.id, started_at).await?;
 
-    if ongoing_gongzuo.is_some() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message":
-                    format!(
-                        "Gongzuo {} is ongoing, so you can't end this gongzuo",
-                        ongoing_gongzuo.unwrap().id
-                    )
-            })),
-        ));
+    if let Some(ongoing_gongzuo) = ongoing_gongzuo {
+        return Err(AppError::Overlap(format!(
+            "Gongzuo {} is ongoing, so you can't start a new gongzuo",
+            ongoing_gongzuo.id
+        )));
     }
 
     let payload = GongzuoPayload {
         started_at,
         ended_at: None,
-        content_kind,
+        category_id,
+        project_id: Some(project_id),
         content,
+        tags: Some(tags),
     };
 
     let gongzuo_id = db
@@ -124,18 +209,19 @@ pub async fn start_gongzuo(
 pub struct GongzuoEndPayload {
     pub gongzuo_id: i32,
     pub content: Option<String>,
+    /// `None` keeps the current tags.
+    pub tags: Option<Vec<String>>,
 }
 
 pub async fn end_gongzuo(
     State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
+    AuthUser { user, .. }: AuthUser,
     Json(payload): Json<GongzuoEndPayload>,
 ) -> Result<impl IntoResponse> {
-    let user = get_user_by_session_token!(db, session_token);
-
     let GongzuoEndPayload {
         gongzuo_id,
         content,
+        tags,
     } = payload;
 
     let Some(gongzuo) = db
@@ -143,68 +229,51 @@ pub async fn end_gongzuo(
         .gongzuo_by_gongzuo_id(gongzuo_id)
         .await?
     else {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": format!("Gongzuo {} not found", gongzuo_id)
-            })),
-        ));
+        return Err(AppError::NotFound(format!(
+            "Gongzuo {} not found",
+            gongzuo_id
+        )));
     };
 
     if gongzuo.ended_at.is_some() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": format!("Gongzuo {} already ended", gongzuo_id)
-            })),
-        ));
+        return Err(AppError::Conflict(format!(
+            "Gongzuo {} already ended",
+            gongzuo_id
+        )));
     }
 
-    if gongzuo.content_kind == ContentKind::NotWork && content.is_some() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message":
-                    format!(
-                        "Gongzuo {} is not work, so content must be None",
-                        gongzuo_id
-                    )
-            })),
-        ));
+    if !gongzuo.counts_as_work && content.is_some() {
+        return Err(AppError::Validation(format!(
+            "Gongzuo {} is not work, so content must be None",
+            gongzuo_id
+        )));
     }
 
-    let Gongzuo {
+    let GongzuoRaw {
         id: gongzuo_id,
         started_at,
-        content_kind,
+        category_id,
         content: original_content,
         ..
-    } = Gongzuo::from(gongzuo);
+    } = gongzuo;
 
     let content = content.unwrap_or(original_content);
 
-    let started_at = started_at.with_timezone(&Utc);
+    let started_at = DateTime::from_naive_utc_and_offset(started_at, Utc);
     let ended_at = Utc::now();
 
     let payload = GongzuoPayload {
         started_at,
         ended_at: Some(ended_at),
-        content_kind,
+        category_id,
+        project_id: None,
         content,
+        tags,
     };
 
-    if let Err(error_message) = db
-        .gongzuo_handler()
-        .update_gongzuo(gongzuo_id, user.id, payload)
-        .await?
-    {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": error_message,
-            })),
-        ));
-    }
+    db.gongzuo_handler()
+        .update_gongzuo(gongzuo_id, user.id, user.id, payload)
+        .await?;
 
     Ok((
         StatusCode::OK,
@@ -215,49 +284,157 @@ pub async fn end_gongzuo(
     ))
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct GongzuoSwitchPayload {
+    #[serde(alias = "content_kind")]
+    pub category_id: i32,
+    pub project_id: Option<i32>,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Ends the ongoing gongzuo and starts a new one at the same instant.
+pub async fn switch_gongzuo(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Json(payload): Json<GongzuoSwitchPayload>,
+) -> Result<impl IntoResponse> {
+    let GongzuoSwitchPayload {
+        category_id,
+        project_id,
+        content,
+        tags,
+    } = payload;
+
+    let switched_at = Utc::now();
+
+    let Some(ongoing_gongzuo) = db
+        .gongzuo_handler()
+        .gongzuo_at(user.id, switched_at)
+        .await?
+    else {
+        return Err(AppError::Conflict(String::from(
+            "No gongzuo is ongoing, so you can't switch gongzuo",
+        )));
+    };
+
+    let payload = GongzuoPayload {
+        started_at: switched_at,
+        ended_at: None,
+        category_id,
+        project_id: Some(project_id),
+        content,
+        tags: Some(tags),
+    };
+
+    let gongzuo_id = db
+        .gongzuo_handler()
+        .switch_gongzuo(ongoing_gongzuo.id, user.id, payload)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "ended_gongzuo_id": ongoing_gongzuo.id,
+            "started_gongzuo_id": gongzuo_id,
+            "switched_at": switched_at,
+        })),
+    ))
+}
+
+/// Starts a break in the ongoing gongzuo. The break doesn't count as worked time.
+pub async fn pause_gongzuo(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+) -> Result<impl IntoResponse> {
+    let paused_at = Utc::now();
+
+    let Some(ongoing_gongzuo) = db.gongzuo_handler().gongzuo_at(user.id, paused_at).await? else {
+        return Err(AppError::Conflict(String::from(
+            "No gongzuo is ongoing, so you can't pause gongzuo",
+        )));
+    };
+
+    db.gongzuo_handler()
+        .pause_gongzuo(ongoing_gongzuo.id, user.id, paused_at)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "gongzuo_id": ongoing_gongzuo.id,
+            "paused_at": paused_at,
+        })),
+    ))
+}
+
+pub async fn resume_gongzuo(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+) -> Result<impl IntoResponse> {
+    let resumed_at = Utc::now();
+
+    let Some(ongoing_gongzuo) = db.gongzuo_handler().gongzuo_at(user.id, resumed_at).await? else {
+        return Err(AppError::Conflict(String::from(
+            "No gongzuo is ongoing, so you can't resume gongzuo",
+        )));
+    };
+
+    db.gongzuo_handler()
+        .resume_gongzuo(ongoing_gongzuo.id, user.id, resumed_at)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "gongzuo_id": ongoing_gongzuo.id,
+            "resumed_at": resumed_at,
+        })),
+    ))
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GongzuoEditPayload {
     pub gongzuo_id: i32,
     pub started_at: DateTime<Utc>,
     pub ended_at: Option<DateTime<Utc>>,
-    pub content_kind: ContentKind,
+    #[serde(alias = "content_kind")]
+    pub category_id: i32,
+    /// Absent leaves the project as it is, and `null` removes it.
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub project_id: Option<Option<i32>>,
     pub content: String,
+    pub tags: Option<Vec<String>>,
 }
 
 pub async fn edit_gongzuo(
     State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
+    AuthUser { user, .. }: AuthUser,
     Json(payload): Json<GongzuoEditPayload>,
 ) -> Result<impl IntoResponse> {
-    let user = get_user_by_session_token!(db, session_token);
-
     let GongzuoEditPayload {
         gongzuo_id,
         started_at,
         ended_at,
-        content_kind,
+        category_id,
+        project_id,
         content,
+        tags,
     } = payload;
 
     let payload = GongzuoPayload {
         started_at,
         ended_at,
-        content_kind,
+        category_id,
+        project_id,
         content,
+        tags,
     };
 
-    if let Err(error_message) = db
-        .gongzuo_handler()
-        .update_gongzuo(gongzuo_id, user.id, payload)
-        .await?
-    {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": error_message,
-            })),
-        ));
-    }
+    db.gongzuo_handler()
+        .update_gongzuo(gongzuo_id, user.id, user.id, payload)
+        .await?;
 
     Ok((
         StatusCode::OK,
@@ -274,25 +451,14 @@ pub struct GongzuoDeletePayload {
 
 pub async fn delete_gongzuo(
     State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
+    AuthUser { user, .. }: AuthUser,
     Json(payload): Json<GongzuoDeletePayload>,
 ) -> Result<impl IntoResponse> {
-    let user = get_user_by_session_token!(db, session_token);
-
     let GongzuoDeletePayload { gongzuo_id } = payload;
 
-    if let Err(error_message) = db
-        .gongzuo_handler()
+    db.gongzuo_handler()
         .delete_gongzuo(gongzuo_id, user.id)
-        .await?
-    {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": error_message,
-            })),
-        ));
-    }
+        .await?;
 
     Ok((
         StatusCode::OK,
@@ -302,27 +468,270 @@ pub async fn delete_gongzuo(
     ))
 }
 
+/// Deleted gongzuos of the user which haven't been purged yet.
+pub async fn trash(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let gongzuos = db
+        .gongzuo_handler()
+        .deleted_gongzuos(user.id)
+        .await?
+        .into_iter()
+        .map(|gongzuo| Gongzuo::from_raw(gongzuo, tz))
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "gongzuos": gongzuos,
+            "retention_days": trash_retention().num_days(),
+        })),
+    ))
+}
+
+pub async fn restore_gongzuo(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(gongzuo_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    db.gongzuo_handler()
+        .restore_gongzuo(gongzuo_id, user.id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Gongzuo restored",
+        })),
+    ))
+}
+
+/// The ongoing gongzuo and how long it has been running, or `null` for both.
+fn current_gongzuo_json(
+    gongzuo: Option<GongzuoRaw>,
+    now: DateTime<Utc>,
+    tz: Tz,
+) -> serde_json::Value {
+    // 休憩中の時間は含めない。
+    let elapsed_seconds = gongzuo
+        .as_ref()
+        .map(|gongzuo| gongzuo.worked_seconds(now.naive_utc()));
+    let gongzuo = gongzuo.map(|gongzuo| Gongzuo::from_raw(gongzuo, tz));
+
+    json!({
+        "gongzuo": gongzuo,
+        "elapsed_seconds": elapsed_seconds,
+    })
+}
+
+pub async fn current_gongzuo(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+    let now = Utc::now();
+    let gongzuo = db.gongzuo_handler().gongzuo_at(user.id, now).await?;
+
+    Ok((StatusCode::OK, Json(current_gongzuo_json(gongzuo, now, tz))))
+}
+
+pub async fn current_gongzuo_by_user_id(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(user_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    // 見られないユーザーは存在しないものとして扱う。
+    if !can_read(&db, &user, user_id).await? {
+        return Err(AppError::NotFound(format!("User {} not found", user_id)));
+    }
+
+    let now = Utc::now();
+    let gongzuo = db.gongzuo_handler().gongzuo_at(user_id, now).await?;
+
+    Ok((StatusCode::OK, Json(current_gongzuo_json(gongzuo, now, tz))))
+}
+
 pub async fn gongzuo_by_id(
     State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
+    AuthUser { user, .. }: AuthUser,
     Path(gongzuo_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
 ) -> Result<impl IntoResponse> {
-    let _user = get_user_by_session_token!(db, session_token);
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let gongzuo = match db
+        .gongzuo_handler()
+        .gongzuo_by_gongzuo_id(gongzuo_id)
+        .await?
+    {
+        Some(gongzuo) if can_read(&db, &user, gongzuo.user_id).await? => gongzuo,
+        // 見られない gongzuo があることも知らせない。
+        _ => {
+            return Err(AppError::NotFound(format!(
+                "Gongzuo {} not found",
+                gongzuo_id
+            )))
+        }
+    };
+
+    let gongzuo = Gongzuo::from_raw(gongzuo, tz);
+
+    Ok((StatusCode::OK, Json(json!(gongzuo))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GongzuoUpdatePayload {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    #[serde(alias = "content_kind")]
+    pub category_id: i32,
+    /// Absent leaves the project as it is, and `null` removes it.
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub project_id: Option<Option<i32>>,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+}
+
+/// Edits a gongzuo of any user. The route requires the `gongzuo.edit_others` permission.
+/// The change is recorded in the history as made by the current user.
+pub async fn update_gongzuo_by_id(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(gongzuo_id): Path<i32>,
+    Json(payload): Json<GongzuoUpdatePayload>,
+) -> Result<impl IntoResponse> {
+    let GongzuoUpdatePayload {
+        started_at,
+        ended_at,
+        category_id,
+        project_id,
+        content,
+        tags,
+    } = payload;
 
     let Some(gongzuo) = db
         .gongzuo_handler()
         .gongzuo_by_gongzuo_id(gongzuo_id)
         .await?
     else {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": format!("Gongzuo {} not found", gongzuo_id)
-            })),
-        ));
+        return Err(AppError::NotFound(format!(
+            "Gongzuo {} not found",
+            gongzuo_id
+        )));
     };
 
-    let gongzuo = Gongzuo::from(gongzuo);
+    let payload = GongzuoPayload {
+        started_at,
+        ended_at,
+        category_id,
+        project_id,
+        content,
+        tags,
+    };
 
-    Ok((StatusCode::OK, Json(json!(gongzuo))))
+    db.gongzuo_handler()
+        .update_gongzuo(gongzuo_id, gongzuo.user_id, user.id, payload)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Gongzuo updated",
+        })),
+    ))
+}
+
+/// Every create, update, delete, restore, pause and resume of the gongzuo, oldest first.
+pub async fn gongzuo_history(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(gongzuo_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let revisions = db.revision_handler().gongzuo_revisions(gongzuo_id).await?;
+
+    // 完全に削除された gongzuo の履歴も返すので、gongzuo ではなく履歴から持ち主を調べる。
+    let readable = match revisions.first().and_then(|revision| revision.owner_id()) {
+        Some(owner_id) => can_read(&db, &user, owner_id).await?,
+        None => false,
+    };
+    if !readable {
+        return Err(AppError::NotFound(format!(
+            "Gongzuo {} not found",
+            gongzuo_id
+        )));
+    }
+
+    let revisions = revisions
+        .into_iter()
+        .map(|revision| GongzuoRevision::from_raw(revision, tz))
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!({ "revisions": revisions }))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit_payload(project_id: &str) -> GongzuoEditPayload {
+        let json = format!(
+            r#"{{
+                "gongzuo_id": 1,
+                "started_at": "2024-01-01T00:00:00Z",
+                "ended_at": null,
+                "category_id": 0,
+                "content": "coding"
+                {}
+            }}"#,
+            project_id
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn edit_without_project_id_keeps_the_project() {
+        assert_eq!(edit_payload("").project_id, None);
+    }
+
+    #[test]
+    fn edit_with_null_project_id_removes_the_project() {
+        assert_eq!(
+            edit_payload(r#", "project_id": null"#).project_id,
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn edit_with_project_id_sets_the_project() {
+        assert_eq!(
+            edit_payload(r#", "project_id": 3"#).project_id,
+            Some(Some(3))
+        );
+    }
+
+    #[test]
+    fn update_without_project_id_keeps_the_project() {
+        let payload: GongzuoUpdatePayload = serde_json::from_str(
+            r#"{
+                "started_at": "2024-01-01T00:00:00Z",
+                "ended_at": null,
+                "category_id": 0,
+                "content": "coding"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(payload.project_id, None);
+    }
 }
@@ -0,0 +1,185 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::AdminUser;
+use crate::billing::{validate_rounding_minutes, Rounding};
+use crate::db::invoice::{
+    Invoice, InvoiceDraft, InvoiceHandlerTrait, InvoiceLineItem, InvoiceRequest,
+};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::util::timezone::TimeZoneQuery;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InvoiceListQuery {
+    pub client_id: Option<i32>,
+}
+
+pub async fn invoices(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Query(query): Query<InvoiceListQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let invoices = db
+        .invoice_handler()
+        .invoices(query.client_id)
+        .await?
+        .into_iter()
+        .map(|invoice| Invoice::from_raw(invoice, tz))
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!({ "invoices": invoices }))))
+}
+
+pub async fn invoice_by_id(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(invoice_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let invoice = db
+        .invoice_handler()
+        .invoice_by_id(invoice_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Invoice {} not found", invoice_id)))?;
+    let line_items = db
+        .invoice_handler()
+        .invoice_line_items(invoice_id)
+        .await?
+        .into_iter()
+        .map(|line_item| InvoiceLineItem::from_raw(line_item, tz))
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "invoice": Invoice::from_raw(invoice, tz),
+            "line_items": line_items,
+        })),
+    ))
+}
+
+fn default_rounding_minutes() -> i32 {
+    1
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InvoicePayload {
+    pub client_id: i32,
+    /// First day of the invoice (inclusive).
+    pub from: NaiveDate,
+    /// Last day of the invoice (inclusive).
+    pub to: NaiveDate,
+    /// Each gongzuo is rounded to a multiple of this, e.g. 15.
+    #[serde(default = "default_rounding_minutes")]
+    pub rounding_minutes: i32,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+impl InvoicePayload {
+    fn into_request(self, tz: chrono_tz::Tz) -> Result<InvoiceRequest> {
+        let InvoicePayload {
+            client_id,
+            from,
+            to,
+            rounding_minutes,
+            rounding,
+        } = self;
+
+        if to < from {
+            return Err(AppError::Validation(String::from(
+                "to must not be before from",
+            )));
+        }
+        validate_rounding_minutes(rounding_minutes)?;
+
+        Ok(InvoiceRequest {
+            client_id,
+            from,
+            to,
+            tz,
+            rounding_minutes,
+            rounding,
+        })
+    }
+}
+
+/// Shows what the invoice would look like without issuing it.
+pub async fn preview_invoice(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Query(tz): Query<TimeZoneQuery>,
+    Json(payload): Json<InvoicePayload>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+    let request = payload.into_request(tz)?;
+
+    let InvoiceDraft {
+        currency,
+        total_amount,
+        line_items,
+    } = db.invoice_handler().draft_invoice(&request).await?;
+
+    let line_items = line_items
+        .into_iter()
+        .map(|line_item| InvoiceLineItem::from_raw(line_item, tz))
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "currency": currency,
+            "total_amount": total_amount,
+            "line_items": line_items,
+        })),
+    ))
+}
+
+/// Issues an invoice. Its gongzuos can't be edited or deleted afterwards.
+pub async fn issue_invoice(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Query(tz): Query<TimeZoneQuery>,
+    Json(payload): Json<InvoicePayload>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+    let request = payload.into_request(tz)?;
+
+    let invoice_id = db
+        .invoice_handler()
+        .issue_invoice(&request, user.id)
+        .await?;
+
+    let invoice = db
+        .invoice_handler()
+        .invoice_by_id(invoice_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Invoice {} not found", invoice_id)))?;
+    let line_items = db
+        .invoice_handler()
+        .invoice_line_items(invoice_id)
+        .await?
+        .into_iter()
+        .map(|line_item| InvoiceLineItem::from_raw(line_item, tz))
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "invoice": Invoice::from_raw(invoice, tz),
+            "line_items": line_items,
+        })),
+    ))
+}
@@ -1,14 +1,24 @@
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::{response::IntoResponse, Json};
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header::USER_AGENT, HeaderMap, StatusCode};
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
 use serde::Deserialize;
 use serde_json::json;
 
+use crate::auth::session_cookie;
+use crate::db::totp::TotpHandlerTrait;
 use crate::db::user::{UserHandlerTrait, UserRaw};
 use crate::db::DB;
-use crate::error::Result;
-use crate::password;
+use crate::error::{AppError, Result};
+use crate::login_throttle;
+use crate::password::{self, Verification};
 use crate::session::create_session_token;
+use crate::totp;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct LoginPayload {
@@ -18,50 +28,159 @@ pub struct LoginPayload {
 
 pub async fn login(
     State(db): State<DB>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<LoginPayload>,
-) -> Result<impl IntoResponse> {
+) -> Result<Response> {
     let LoginPayload { username, password } = payload;
+    let ip = addr.ip().to_string();
+
+    login_throttle::ensure_not_locked(&db, &username, &ip).await?;
+
+    let user = db.user_handler().get_user_by_username(&username).await?;
+    let verification = match &user {
+        Some(user) => password::verify(&user.password, &user.salt, &password)?,
+        None => password::verify_dummy(&password)?,
+    };
+
+    // ユーザーが存在しないのか、無効化されているのか、パスワードが違うのかは区別しない。
+    // 検証は無効化されたユーザーでも済ませ、応答時間も揃える。
+    let user = user.filter(|user| user.deactivated_at.is_none());
+    let (Some(user), Verification::Valid { needs_rehash }) = (user, verification) else {
+        login_throttle::record_failure(&db, &username, &ip).await?;
+        return Err(AppError::Unauthorized(String::from(
+            "Invalid username or password",
+        )));
+    };
+
+    // 古いハッシュ (PBKDF2 や古いパラメータ) は平文が分かるログイン時に置き換える。
+    if needs_rehash {
+        let password_hash = password::hash(&password)?;
+        db.user_handler()
+            .update_password_hash(user.id, &password_hash)
+            .await?;
+    }
+
+    let totp_enabled = db
+        .totp_handler()
+        .totp_credential(user.id)
+        .await?
+        .is_some_and(|credential| credential.confirmed_at.is_some());
+    if !totp_enabled {
+        return start_session(&db, &user, &headers, &ip).await;
+    }
+
+    // 失敗の記録は消さずに残し、コードの総当たりも同じように制限する。
+    let (challenge_token, challenge_token_hash) = password::create_token()?;
+    let expires_at = Utc::now().naive_utc() + totp::challenge_ttl();
+    db.totp_handler()
+        .create_login_challenge(user.id, &challenge_token_hash, expires_at)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Two-factor authentication required",
+            "two_factor_required": true,
+            "challenge_token": challenge_token,
+            "expires_in_seconds": totp::challenge_ttl().num_seconds(),
+        })),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TotpLoginPayload {
+    pub challenge_token: String,
+    /// A code from the authenticator app, or one of the recovery codes.
+    pub code: String,
+}
+
+/// Second step of the login of users with two-factor authentication.
+/// Exchanges the challenge from `/login` and a code for a session.
+/// The challenge is used up even if the code is wrong.
+pub async fn login_totp(
+    State(db): State<DB>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<TotpLoginPayload>,
+) -> Result<Response> {
+    let TotpLoginPayload {
+        challenge_token,
+        code,
+    } = payload;
+    let ip = addr.ip().to_string();
+    let challenge_token_hash = password::hash_token(&challenge_token);
+
+    // 検証の前に使い切る。コードを間違えたら /login からやり直すので、
+    // 一つのチャレンジでコードを何度も試すことはできない。
+    let invalid_challenge = || AppError::Unauthorized(String::from("Invalid or expired challenge"));
+    let user_id = db
+        .totp_handler()
+        .consume_login_challenge(&challenge_token_hash)
+        .await?
+        .ok_or_else(invalid_challenge)?;
+    let user = db
+        .user_handler()
+        .get_user_by_id(user_id)
+        .await?
+        .filter(|user| user.deactivated_at.is_none())
+        .ok_or_else(invalid_challenge)?;
 
-    let Some(user) = db.user_handler().get_user_by_username(&username).await? else {
-        return Err(anyhow::anyhow!("User {} not found", &username).into());
+    login_throttle::ensure_not_locked(&db, &user.username, &ip).await?;
+
+    if !verify_second_factor(&db, user.id, &code).await? {
+        login_throttle::record_failure(&db, &user.username, &ip).await?;
+        return Err(AppError::Unauthorized(String::from("Invalid code")));
+    }
+
+    start_session(&db, &user, &headers, &ip).await
+}
+
+/// A TOTP code which hasn't been used yet, or an unused recovery code.
+async fn verify_second_factor(db: &DB, user_id: i32, code: &str) -> Result<bool> {
+    let Some(credential) = db.totp_handler().totp_credential(user_id).await? else {
+        return Ok(false);
     };
+    if credential.confirmed_at.is_none() {
+        return Ok(false);
+    }
 
-    let UserRaw {
-        id: user_id,
-        password: hashed_password,
-        salt,
-        session_token,
-        ..
-    } = user;
-
-    let is_valid = password::verify_with_salt(salt, hashed_password, password)?;
-
-    if is_valid {
-        let session_token = match session_token {
-            Some(session_token) => session_token,
-            None => {
-                let session_token = create_session_token();
-                db.user_handler()
-                    .update_session_token(user_id, &session_token)
-                    .await?;
-
-                session_token
-            }
-        };
-
-        Ok((
-            StatusCode::OK,
-            Json(json!({
-                "message": "Login successful",
-                "session_token": session_token,
-            })),
-        ))
-    } else {
-        Ok((
-            StatusCode::UNAUTHORIZED,
-            Json(json!({
-                "message": "Login failed"
-            })),
-        ))
+    if let Some(step) = totp::verify(
+        &credential.secret,
+        code,
+        Utc::now().naive_utc(),
+        credential.last_used_step,
+    ) {
+        return db.totp_handler().use_step(user_id, step).await;
     }
+
+    db.totp_handler()
+        .use_recovery_code(user_id, &totp::hash_recovery_code(code))
+        .await
+}
+
+async fn start_session(db: &DB, user: &UserRaw, headers: &HeaderMap, ip: &str) -> Result<Response> {
+    login_throttle::record_success(db, &user.username).await?;
+
+    // 端末ごとに別のsessionを発行する。
+    let session_token = create_session_token();
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok());
+    db.user_handler()
+        .create_session(user.id, &session_token, user_agent, Some(ip))
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        [session_cookie(&session_token)],
+        Json(json!({
+            "message": "Login successful",
+            "two_factor_required": false,
+            "session_token": session_token,
+            "password_reset_required": user.password_reset_required,
+        })),
+    )
+        .into_response())
 }
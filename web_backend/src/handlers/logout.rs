@@ -3,8 +3,9 @@ use serde::Deserialize;
 use serde_json::json;
 
 use crate::{
+    auth::{clear_session_cookie, AuthUser},
     db::{user::UserHandlerTrait, DB},
-    error::Result,
+    error::{AppError, Result},
 };
 
 #[derive(Deserialize, Debug, Clone)]
@@ -12,29 +13,35 @@ pub struct LogoutPayload {
     pub session_token: String,
 }
 
+/// Revokes the session given in the body, or the session which authenticated the request.
 pub async fn logout(
     State(db): State<DB>,
-    Json(payload): Json<LogoutPayload>,
+    auth_user: Option<AuthUser>,
+    payload: Option<Json<LogoutPayload>>,
 ) -> Result<impl IntoResponse> {
-    let LogoutPayload { session_token } = payload;
+    let session_token = match (payload, auth_user) {
+        (Some(Json(LogoutPayload { session_token })), _) => db
+            .user_handler()
+            .ensure_session_token(&session_token)
+            .await?
+            .map(|_| session_token),
+        (None, Some(AuthUser { session_token, .. })) => Some(session_token),
+        (None, None) => None,
+    };
 
-    let Some(user) = db
-        .user_handler()
-        .ensure_session_token(&session_token)
-        .await?
-    else {
-        return Ok((
-            StatusCode::UNAUTHORIZED,
-            Json(json!({
-                "message": "Invalid session token"
-            })),
-        ));
+    let Some(session_token) = session_token else {
+        return Err(AppError::Unauthorized(String::from(
+            "Invalid session token",
+        )));
     };
 
-    db.user_handler().remove_session_token(user.id).await?;
+    db.user_handler()
+        .remove_session_token(&session_token)
+        .await?;
 
     Ok((
         StatusCode::OK,
+        [clear_session_cookie()],
         Json(json!({
             "message": "Logout successful"
         })),
@@ -0,0 +1,55 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    db::{user::UserHandlerTrait, DB},
+    error::{AppError, Result},
+    password,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PasswordResetPayload {
+    /// Issued by an admin at `/admin/users/:id/reset_token`.
+    pub token: String,
+    pub new_password: String,
+}
+
+/// Sets a new password with a reset token instead of the current password.
+/// Every session and access token of the user is revoked,
+/// so the user logs in again with the new password.
+pub async fn reset_password(
+    State(db): State<DB>,
+    Json(payload): Json<PasswordResetPayload>,
+) -> Result<impl IntoResponse> {
+    let PasswordResetPayload {
+        token,
+        new_password,
+    } = payload;
+
+    if new_password.is_empty() {
+        return Err(AppError::Validation(String::from(
+            "new_password must not be empty",
+        )));
+    }
+
+    let password_hash = password::hash(&new_password)?;
+
+    let user_id = db
+        .user_handler()
+        .reset_password_with_token(&password::hash_token(&token), &password_hash)
+        .await?;
+
+    if user_id.is_none() {
+        return Err(AppError::Unauthorized(String::from(
+            "Invalid or expired reset token",
+        )));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Password changed",
+        })),
+    ))
+}
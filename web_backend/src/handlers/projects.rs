@@ -0,0 +1,190 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::{AdminUser, AuthUser};
+use crate::db::project::{ProjectFilter, ProjectHandlerTrait, ProjectPayload};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Names of clients and projects are trimmed and must not be empty.
+pub fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "name must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    Ok(name.to_string())
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ProjectListQuery {
+    pub client_id: Option<i32>,
+    /// Only projects which the current user is a member of.
+    pub mine: bool,
+    pub include_archived: bool,
+}
+
+pub async fn projects(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(query): Query<ProjectListQuery>,
+) -> Result<impl IntoResponse> {
+    let ProjectListQuery {
+        client_id,
+        mine,
+        include_archived,
+    } = query;
+
+    let filter = ProjectFilter {
+        client_id,
+        member_user_id: mine.then_some(user.id),
+        include_archived,
+    };
+
+    let projects = db.project_handler().projects(&filter).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "projects": projects }))))
+}
+
+pub async fn project_by_id(
+    State(db): State<DB>,
+    _: AuthUser,
+    Path(project_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    let project = db
+        .project_handler()
+        .project_by_id(project_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Project {} not found", project_id)))?;
+
+    Ok((StatusCode::OK, Json(json!({ "project": project }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProjectRequest {
+    pub client_id: i32,
+    pub name: String,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl ProjectRequest {
+    fn validate(self) -> Result<ProjectPayload> {
+        Ok(ProjectPayload {
+            client_id: self.client_id,
+            name: validate_name(&self.name)?,
+            archived: self.archived,
+        })
+    }
+}
+
+pub async fn create_project(
+    State(db): State<DB>,
+    _: AdminUser,
+    Json(payload): Json<ProjectRequest>,
+) -> Result<impl IntoResponse> {
+    let project = db
+        .project_handler()
+        .create_project(payload.validate()?)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "project": project }))))
+}
+
+pub async fn update_project(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(project_id): Path<i32>,
+    Json(payload): Json<ProjectRequest>,
+) -> Result<impl IntoResponse> {
+    let project = db
+        .project_handler()
+        .update_project(project_id, payload.validate()?)
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "project": project }))))
+}
+
+pub async fn delete_project(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(project_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    db.project_handler().delete_project(project_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Project deleted"
+        })),
+    ))
+}
+
+pub async fn project_members(
+    State(db): State<DB>,
+    _: AuthUser,
+    Path(project_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    let members = db.project_handler().project_members(project_id).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "members": members }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProjectMemberPayload {
+    pub user_id: i32,
+}
+
+pub async fn add_project_member(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(project_id): Path<i32>,
+    Json(payload): Json<ProjectMemberPayload>,
+) -> Result<impl IntoResponse> {
+    let added = db
+        .project_handler()
+        .add_project_member(project_id, payload.user_id)
+        .await?;
+
+    if !added {
+        return Ok((
+            StatusCode::OK,
+            Json(json!({
+                "message": "User is already a member"
+            })),
+        ));
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "message": "Member added"
+        })),
+    ))
+}
+
+pub async fn remove_project_member(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path((project_id, user_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse> {
+    db.project_handler()
+        .remove_project_member(project_id, user_id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Member removed"
+        })),
+    ))
+}
@@ -0,0 +1,106 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::AdminUser;
+use crate::db::rate::{BillableRatePayload, RateHandlerTrait};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RateListQuery {
+    pub project_id: Option<i32>,
+    pub user_id: Option<i32>,
+}
+
+pub async fn rates(
+    State(db): State<DB>,
+    _: AdminUser,
+    Query(query): Query<RateListQuery>,
+) -> Result<impl IntoResponse> {
+    let rates = db
+        .rate_handler()
+        .rates(query.project_id, query.user_id)
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "rates": rates }))))
+}
+
+fn default_currency() -> String {
+    String::from("JPY")
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateRequest {
+    pub project_id: Option<i32>,
+    pub user_id: Option<i32>,
+    /// In the minor unit of `currency`.
+    pub hourly_rate: i64,
+    /// ISO 4217 code such as `JPY` or `USD`.
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub effective_from: NaiveDate,
+}
+
+pub async fn create_rate(
+    State(db): State<DB>,
+    _: AdminUser,
+    Json(payload): Json<RateRequest>,
+) -> Result<impl IntoResponse> {
+    let RateRequest {
+        project_id,
+        user_id,
+        hourly_rate,
+        currency,
+        effective_from,
+    } = payload;
+
+    if project_id.is_none() && user_id.is_none() {
+        return Err(AppError::Validation(String::from(
+            "project_id or user_id is required",
+        )));
+    }
+    if hourly_rate < 0 {
+        return Err(AppError::Validation(String::from(
+            "hourly_rate must not be negative",
+        )));
+    }
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(AppError::Validation(format!(
+            "currency must be an ISO 4217 code: {}",
+            currency
+        )));
+    }
+
+    let payload = BillableRatePayload {
+        project_id,
+        user_id,
+        hourly_rate,
+        currency,
+        effective_from,
+    };
+
+    let rate = db.rate_handler().create_rate(payload).await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "rate": rate }))))
+}
+
+pub async fn delete_rate(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(rate_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    db.rate_handler().delete_rate(rate_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Rate deleted"
+        })),
+    ))
+}
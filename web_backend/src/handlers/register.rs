@@ -1,66 +1,55 @@
-use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
-};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::handlers::gongzuo::session_token_invalid_error;
 use crate::{
+    auth::AuthUser,
     db::{
         user::{User, UserHandlerTrait},
         DB,
     },
     error::AppError,
-    get_user_by_session_token,
+    util::timezone::{parse_time_zone, DEFAULT_TIME_ZONE},
 };
 
-use super::gongzuo::SessionQuery;
-
 #[derive(Deserialize, Debug, Clone)]
 pub struct UserPayload {
     pub username: String,
     pub password: String,
+    pub time_zone: Option<String>,
 }
 
 pub async fn register(
     State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
+    AuthUser { user: actor, .. }: AuthUser,
     Json(payload): Json<UserPayload>,
 ) -> Result<impl IntoResponse, AppError> {
-    let user = get_user_by_session_token!(db, session_token);
-    if !user.is_admin {
-        return Ok((
-            StatusCode::UNAUTHORIZED,
-            Json(json!({
-                "message": "Only admin can register new users"
-            })),
-        ));
-    }
-    let UserPayload { username, password } = payload;
+    let UserPayload {
+        username,
+        password,
+        time_zone,
+    } = payload;
+
+    let tz = match time_zone {
+        Some(time_zone) => parse_time_zone(&time_zone)?,
+        None => DEFAULT_TIME_ZONE,
+    };
 
     let user = db.user_handler().get_user_by_username(&username).await?;
 
     if user.is_some() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": "User already exists"
-            })),
-        ));
+        return Err(AppError::Conflict(String::from("User already exists")));
     }
 
-    let (salt, hashed_password) = crate::password::derive(password)?;
+    let password_hash = crate::password::hash(&password)?;
 
     let user = db
         .user_handler()
-        .register_user(&username, &hashed_password, &salt)
+        .register_user(&username, &password_hash, tz.name(), actor.id)
         .await?;
 
     Ok((
         StatusCode::CREATED,
-        Json(json!({ "user": User::from(user) })),
+        Json(json!({ "user": User::from_raw(user, tz) })),
     ))
 }
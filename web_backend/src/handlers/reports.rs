@@ -0,0 +1,224 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::{Days, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::json;
+use serde_with::NoneAsEmptyString;
+
+use crate::access::{can_read, readable_users};
+use crate::auth::AuthUser;
+use crate::db::gongzuo::GongzuoHandlerTrait;
+use crate::db::project::{ProjectFilter, ProjectHandlerTrait};
+use crate::db::tag::parse_tag_list;
+use crate::db::user::{UserHandlerTrait, UserRaw};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::report::{start_of_day, summarize, summarize_projects, Granularity, Summary};
+use crate::util::timezone::TimeZoneQuery;
+
+/// Longest range a single report may cover.
+const MAX_REPORT_DAYS: i64 = 366 * 2;
+
+#[serde_with::serde_as]
+#[derive(Deserialize, Debug, Clone)]
+pub struct SummaryQuery {
+    pub granularity: Granularity,
+    /// First day of the report (inclusive).
+    pub from: NaiveDate,
+    /// Last day of the report (inclusive).
+    pub to: NaiveDate,
+    pub user_id: Option<i32>,
+    pub project_id: Option<i32>,
+    /// Comma-separated tags. Only gongzuos which have all of them are summed up.
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    pub tags: Option<String>,
+}
+
+fn validate_range(from: NaiveDate, to: NaiveDate) -> Result<()> {
+    if to < from {
+        return Err(AppError::Validation(String::from(
+            "to must not be before from",
+        )));
+    }
+    if (to - from).num_days() > MAX_REPORT_DAYS {
+        return Err(AppError::Validation(format!(
+            "A report can cover at most {} days",
+            MAX_REPORT_DAYS
+        )));
+    }
+    Ok(())
+}
+
+/// Time zone in which the report splits days.
+/// Also rejects `user_id` if the user can't read the gongzuos of that user.
+async fn report_time_zone(
+    db: &DB,
+    user: &UserRaw,
+    user_id: Option<i32>,
+    tz: TimeZoneQuery,
+) -> Result<Tz> {
+    // 日の境界は、対象ユーザーが指定されていればそのユーザーの time zone で決める。
+    let time_zone = match user_id {
+        Some(user_id) => {
+            let not_found = || AppError::NotFound(format!("User {} not found", user_id));
+            if !can_read(db, user, user_id).await? {
+                return Err(not_found());
+            }
+            db.user_handler()
+                .get_user_by_id(user_id)
+                .await?
+                .ok_or_else(not_found)?
+                .time_zone
+        }
+        None => user.time_zone.clone(),
+    };
+    tz.resolve(&time_zone)
+}
+
+pub async fn summary(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(query): Query<SummaryQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let summary = summary_of(&db, &user, query, tz, None).await?;
+
+    Ok((StatusCode::OK, Json(json!(summary))))
+}
+
+/// Summary of the gongzuos `user` can read. `user_ids` further limits whose gongzuos are summed up.
+pub async fn summary_of(
+    db: &DB,
+    user: &UserRaw,
+    query: SummaryQuery,
+    tz: TimeZoneQuery,
+    user_ids: Option<&[i32]>,
+) -> Result<Summary> {
+    let SummaryQuery {
+        granularity,
+        from,
+        to,
+        user_id,
+        project_id,
+        tags,
+    } = query;
+
+    let tags = tags.as_deref().map(parse_tag_list).transpose()?;
+
+    validate_range(from, to)?;
+
+    if let (Some(user_id), Some(user_ids)) = (user_id, user_ids) {
+        if !user_ids.contains(&user_id) {
+            return Err(AppError::NotFound(format!("User {} not found", user_id)));
+        }
+    }
+
+    let tz = report_time_zone(db, user, user_id, tz).await?;
+
+    let mut gongzuos = db
+        .gongzuo_handler()
+        .gongzuos_overlapping(
+            start_of_day(from, tz),
+            start_of_day(to + Days::new(1), tz),
+            user_id,
+            tags.as_deref(),
+            project_id,
+        )
+        .await?;
+
+    let readable = readable_users(db, user).await?;
+    gongzuos.retain(|gongzuo| {
+        readable.contains(gongzuo.user_id)
+            && user_ids.is_none_or(|user_ids| user_ids.contains(&gongzuo.user_id))
+    });
+
+    Ok(summarize(
+        &gongzuos,
+        granularity,
+        from,
+        to,
+        tz,
+        Utc::now().naive_utc(),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProjectReportQuery {
+    /// First day of the report (inclusive).
+    pub from: NaiveDate,
+    /// Last day of the report (inclusive).
+    pub to: NaiveDate,
+    pub user_id: Option<i32>,
+    /// Only projects of this client. Gongzuos without a project are left out then.
+    pub client_id: Option<i32>,
+}
+
+/// Time spent per project within `from..=to`.
+pub async fn projects(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(query): Query<ProjectReportQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let ProjectReportQuery {
+        from,
+        to,
+        user_id,
+        client_id,
+    } = query;
+
+    validate_range(from, to)?;
+
+    let tz = report_time_zone(&db, &user, user_id, tz).await?;
+
+    let projects = db
+        .project_handler()
+        .projects(&ProjectFilter {
+            client_id,
+            member_user_id: None,
+            include_archived: true,
+        })
+        .await?;
+
+    let mut gongzuos = db
+        .gongzuo_handler()
+        .gongzuos_overlapping(
+            start_of_day(from, tz),
+            start_of_day(to + Days::new(1), tz),
+            user_id,
+            None,
+            None,
+        )
+        .await?;
+
+    let readable = readable_users(&db, &user).await?;
+    gongzuos.retain(|gongzuo| readable.contains(gongzuo.user_id));
+
+    if client_id.is_some() {
+        gongzuos.retain(|gongzuo| {
+            projects
+                .iter()
+                .any(|project| Some(project.id) == gongzuo.project_id)
+        });
+    }
+
+    let summaries = summarize_projects(
+        &gongzuos,
+        &projects,
+        start_of_day(from, tz),
+        start_of_day(to + Days::new(1), tz),
+        Utc::now().naive_utc(),
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "time_zone": tz.name(),
+            "projects": summaries,
+        })),
+    ))
+}
@@ -0,0 +1,132 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::{AdminUser, AuthUser};
+use crate::db::role::{Permission, RoleHandlerTrait};
+use crate::db::user::UserHandlerTrait;
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::handlers::projects::validate_name;
+
+pub async fn roles(State(db): State<DB>, _: AdminUser) -> Result<impl IntoResponse> {
+    let roles = db.role_handler().roles(None).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "roles": roles }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RolePayload {
+    pub name: String,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+pub async fn create_role(
+    State(db): State<DB>,
+    _: AdminUser,
+    Json(payload): Json<RolePayload>,
+) -> Result<impl IntoResponse> {
+    let name = validate_name(&payload.name)?;
+
+    let role = db
+        .role_handler()
+        .create_role(&name, &payload.permissions)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "role": role }))))
+}
+
+/// Replaces the name and the permissions of the role.
+pub async fn update_role(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(role_id): Path<i32>,
+    Json(payload): Json<RolePayload>,
+) -> Result<impl IntoResponse> {
+    let name = validate_name(&payload.name)?;
+
+    let role = db
+        .role_handler()
+        .update_role(role_id, &name, &payload.permissions)
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "role": role }))))
+}
+
+pub async fn delete_role(
+    State(db): State<DB>,
+    _: AdminUser,
+    Path(role_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    db.role_handler().delete_role(role_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Role deleted",
+        })),
+    ))
+}
+
+/// Roles of a user. Users can see their own roles.
+pub async fn user_roles(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(user_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    if !user.is_admin && user.id != user_id {
+        return Err(AppError::Forbidden(String::from(
+            "Only admin can see roles of other users",
+        )));
+    }
+
+    if db.user_handler().get_user_by_id(user_id).await?.is_none() {
+        return Err(AppError::NotFound(format!("User {} not found", user_id)));
+    }
+
+    let roles = db.role_handler().roles(Some(user_id)).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "roles": roles }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserRolesPayload {
+    pub role_ids: Vec<i32>,
+}
+
+/// Replaces the roles of a user.
+pub async fn update_user_roles(
+    State(db): State<DB>,
+    AdminUser { user, .. }: AdminUser,
+    Path(user_id): Path<i32>,
+    Json(payload): Json<UserRolesPayload>,
+) -> Result<impl IntoResponse> {
+    db.role_handler()
+        .set_user_roles(user_id, &payload.role_ids, user.id)
+        .await?;
+
+    let roles = db.role_handler().roles(Some(user_id)).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "roles": roles }))))
+}
+
+/// Permissions of the current user. Admins have all of them.
+pub async fn my_permissions(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+) -> Result<impl IntoResponse> {
+    let permissions = if user.is_admin {
+        Permission::ALL
+            .into_iter()
+            .map(|permission| permission.as_str().to_string())
+            .collect()
+    } else {
+        db.role_handler().user_permissions(user.id).await?
+    };
+
+    Ok((StatusCode::OK, Json(json!({ "permissions": permissions }))))
+}
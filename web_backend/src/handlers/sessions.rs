@@ -0,0 +1,79 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::{clear_session_cookie, AuthUser};
+use crate::db::user::{Session, UserHandlerTrait};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::util::timezone::TimeZoneQuery;
+
+pub async fn sessions(
+    State(db): State<DB>,
+    AuthUser {
+        user,
+        session_token,
+    }: AuthUser,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let sessions = db.user_handler().sessions_by_user_id(user.id).await?;
+    let current_session_id = sessions
+        .iter()
+        .find(|session| session.token == session_token)
+        .map(|session| session.id);
+    let sessions = sessions
+        .into_iter()
+        .map(|session| Session::from_raw(session, tz))
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "sessions": sessions,
+            "current_session_id": current_session_id,
+        })),
+    ))
+}
+
+pub async fn revoke_session(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(session_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    if !db
+        .user_handler()
+        .revoke_session(user.id, session_id)
+        .await?
+    {
+        return Err(AppError::NotFound(format!(
+            "Session {} not found",
+            session_id
+        )));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Session revoked"
+        })),
+    ))
+}
+
+pub async fn revoke_all_sessions(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+) -> Result<impl IntoResponse> {
+    db.user_handler().revoke_all_sessions(user.id).await?;
+
+    Ok((
+        StatusCode::OK,
+        [clear_session_cookie()],
+        Json(json!({
+            "message": "All sessions revoked"
+        })),
+    ))
+}
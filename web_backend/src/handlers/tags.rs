@@ -0,0 +1,28 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::AuthUser;
+use crate::db::tag::TagHandlerTrait;
+use crate::db::DB;
+use crate::error::Result;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TagListQuery {
+    /// Count only gongzuos of this user.
+    pub user_id: Option<i32>,
+}
+
+pub async fn tags(
+    State(db): State<DB>,
+    _: AuthUser,
+    Query(query): Query<TagListQuery>,
+) -> Result<impl IntoResponse> {
+    let tags = db.tag_handler().tags(query.user_id).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "tags": tags }))))
+}
@@ -0,0 +1,455 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::access::readable_users;
+use crate::auth::AuthUser;
+use crate::db::gongzuo::{Gongzuo, GongzuoFilter, GongzuoHandlerTrait};
+use crate::db::team::{TeamHandlerTrait, TeamMember, TeamRole};
+use crate::db::user::{UserHandlerTrait, UserRaw};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::handlers::gongzuo::{gongzuo_page, GongzuoListQuery};
+use crate::handlers::projects::validate_name;
+use crate::handlers::reports::{summary_of, SummaryQuery};
+use crate::util::timezone::TimeZoneQuery;
+
+/// Role of `user` in the team, failing unless it is at least `required`.
+/// Admins pass every check even if they aren't members, in which case `None` is returned.
+/// Non-members get 404 so that they can't tell whether the team exists.
+async fn require_team_role(
+    db: &DB,
+    user: &UserRaw,
+    team_id: i32,
+    required: TeamRole,
+) -> Result<Option<TeamRole>> {
+    let not_found = || AppError::NotFound(format!("Team {} not found", team_id));
+
+    if db.team_handler().team_by_id(team_id).await?.is_none() {
+        return Err(not_found());
+    }
+
+    let role = db.team_handler().team_role(team_id, user.id).await?;
+    if user.is_admin {
+        return Ok(role);
+    }
+
+    match role {
+        None => Err(not_found()),
+        Some(role) if role < required => Err(AppError::Forbidden(format!(
+            "Only {}s of the team can do this",
+            required.as_str()
+        ))),
+        Some(role) => Ok(Some(role)),
+    }
+}
+
+/// Members of the team whose gongzuos `user` can read.
+async fn readable_members(db: &DB, user: &UserRaw, team_id: i32) -> Result<Vec<TeamMember>> {
+    let readable = readable_users(db, user).await?;
+    let mut members = db.team_handler().team_members(team_id).await?;
+    members.retain(|member| readable.contains(member.user_id));
+    Ok(members)
+}
+
+/// Teams of the current user. Admins see every team.
+pub async fn teams(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+) -> Result<impl IntoResponse> {
+    let member_user_id = (!user.is_admin).then_some(user.id);
+    let teams = db.team_handler().teams(member_user_id).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "teams": teams }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TeamPayload {
+    pub name: String,
+}
+
+/// Creates a team. The current user becomes its owner.
+pub async fn create_team(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Json(payload): Json<TeamPayload>,
+) -> Result<impl IntoResponse> {
+    let name = validate_name(&payload.name)?;
+
+    let team = db.team_handler().create_team(&name, user.id).await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "team": team }))))
+}
+
+pub async fn team_by_id(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Member).await?;
+
+    let team = db
+        .team_handler()
+        .team_by_id(team_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Team {} not found", team_id)))?;
+
+    Ok((StatusCode::OK, Json(json!({ "team": team }))))
+}
+
+pub async fn rename_team(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+    Json(payload): Json<TeamPayload>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Owner).await?;
+
+    let name = validate_name(&payload.name)?;
+    let team = db.team_handler().rename_team(team_id, &name).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "team": team }))))
+}
+
+pub async fn delete_team(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Owner).await?;
+
+    db.team_handler().delete_team(team_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Team deleted"
+        })),
+    ))
+}
+
+pub async fn team_members(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Member).await?;
+
+    let members = db.team_handler().team_members(team_id).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "members": members }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TeamRolePayload {
+    pub role: TeamRole,
+}
+
+/// Only owners can change roles, and a team can't lose its last owner.
+pub async fn update_team_member(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path((team_id, user_id)): Path<(i32, i32)>,
+    Json(payload): Json<TeamRolePayload>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Owner).await?;
+
+    db.team_handler()
+        .update_team_role(team_id, user_id, payload.role)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Role updated",
+            "role": payload.role,
+        })),
+    ))
+}
+
+/// Members can leave by themselves. Managers can remove members, and owners can remove anyone.
+pub async fn remove_team_member(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path((team_id, user_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse> {
+    if user_id == user.id {
+        require_team_role(&db, &user, team_id, TeamRole::Member).await?;
+    } else {
+        let role = require_team_role(&db, &user, team_id, TeamRole::Manager).await?;
+        let target_role = db.team_handler().team_role(team_id, user_id).await?;
+
+        // manager は manager や owner を外せない。
+        if role == Some(TeamRole::Manager) && target_role >= Some(TeamRole::Manager) {
+            return Err(AppError::Forbidden(String::from(
+                "Only owners of the team can remove managers and owners",
+            )));
+        }
+    }
+
+    db.team_handler()
+        .remove_team_member(team_id, user_id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Member removed"
+        })),
+    ))
+}
+
+pub async fn team_invitations(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Manager).await?;
+
+    let invitations = db
+        .team_handler()
+        .team_invitations(Some(team_id), None)
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "invitations": invitations }))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InvitationPayload {
+    pub user_id: i32,
+    #[serde(default = "default_invitation_role")]
+    pub role: TeamRole,
+}
+
+fn default_invitation_role() -> TeamRole {
+    TeamRole::Member
+}
+
+/// Invites a user to the team. Admins and managers of the team can invite.
+pub async fn invite_to_team(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+    Json(payload): Json<InvitationPayload>,
+) -> Result<impl IntoResponse> {
+    let InvitationPayload { user_id, role } = payload;
+
+    require_team_role(&db, &user, team_id, TeamRole::Manager).await?;
+
+    if role == TeamRole::Owner {
+        return Err(AppError::Validation(String::from(
+            "Users can't be invited as owners. Change the role after they join",
+        )));
+    }
+
+    if db.user_handler().get_user_by_id(user_id).await?.is_none() {
+        return Err(AppError::NotFound(format!("User {} not found", user_id)));
+    }
+
+    let invitation = db
+        .team_handler()
+        .invite_to_team(team_id, user_id, role, user.id)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "invitation": invitation })),
+    ))
+}
+
+pub async fn cancel_team_invitation(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path((team_id, invitation_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Manager).await?;
+
+    match db
+        .team_handler()
+        .team_invitation_by_id(invitation_id)
+        .await?
+    {
+        Some(invitation) if invitation.team_id == team_id => {}
+        _ => {
+            return Err(AppError::NotFound(format!(
+                "Invitation {} not found",
+                invitation_id
+            )))
+        }
+    }
+
+    db.team_handler()
+        .delete_team_invitation(invitation_id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Invitation cancelled"
+        })),
+    ))
+}
+
+/// Pending invitations to the current user.
+pub async fn my_invitations(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+) -> Result<impl IntoResponse> {
+    let invitations = db
+        .team_handler()
+        .team_invitations(None, Some(user.id))
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "invitations": invitations }))))
+}
+
+/// Only the invited user can accept or decline an invitation.
+async fn ensure_invited(db: &DB, user: &UserRaw, invitation_id: i32) -> Result<()> {
+    match db
+        .team_handler()
+        .team_invitation_by_id(invitation_id)
+        .await?
+    {
+        Some(invitation) if invitation.user_id == user.id => Ok(()),
+        _ => Err(AppError::NotFound(format!(
+            "Invitation {} not found",
+            invitation_id
+        ))),
+    }
+}
+
+pub async fn accept_invitation(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(invitation_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    ensure_invited(&db, &user, invitation_id).await?;
+
+    db.team_handler()
+        .accept_team_invitation(invitation_id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Invitation accepted"
+        })),
+    ))
+}
+
+pub async fn decline_invitation(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(invitation_id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    ensure_invited(&db, &user, invitation_id).await?;
+
+    db.team_handler()
+        .delete_team_invitation(invitation_id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Invitation declined"
+        })),
+    ))
+}
+
+/// Same as `/gongzuo/gongzuos`, limited to members of the team.
+pub async fn team_gongzuos(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+    Query(query): Query<GongzuoListQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    require_team_role(&db, &user, team_id, TeamRole::Member).await?;
+
+    let user_ids = readable_members(&db, &user, team_id)
+        .await?
+        .into_iter()
+        .map(|member| member.user_id)
+        .collect();
+
+    let page = gongzuo_page(&db, query, Some(user_ids), tz).await?;
+
+    Ok((StatusCode::OK, Json(page)))
+}
+
+/// What each member of the team is working on right now.
+pub async fn team_now(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    require_team_role(&db, &user, team_id, TeamRole::Member).await?;
+
+    let members = readable_members(&db, &user, team_id).await?;
+    let now = Utc::now();
+
+    // 同じユーザーの gongzuo は重ならないので、進行中の gongzuo は1人1件まで。
+    let filter = GongzuoFilter {
+        user_ids: Some(members.iter().map(|member| member.user_id).collect()),
+        started_before: Some(now),
+        ongoing_only: true,
+        limit: Some(members.len() as i64),
+        ..Default::default()
+    };
+    let mut ongoing_gongzuos = db.gongzuo_handler().gongzuos(&filter).await?;
+
+    let members = members
+        .into_iter()
+        .map(|member| {
+            let gongzuo = ongoing_gongzuos
+                .iter()
+                .position(|gongzuo| gongzuo.user_id == member.user_id)
+                .map(|i| ongoing_gongzuos.swap_remove(i));
+            let elapsed_seconds = gongzuo
+                .as_ref()
+                .map(|gongzuo| gongzuo.worked_seconds(now.naive_utc()));
+
+            json!({
+                "user_id": member.user_id,
+                "username": member.username,
+                "role": member.role,
+                "gongzuo": gongzuo.map(|gongzuo| Gongzuo::from_raw(gongzuo, tz)),
+                "elapsed_seconds": elapsed_seconds,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!({ "members": members }))))
+}
+
+/// Same as `/reports/summary`, limited to members of the team.
+pub async fn team_summary(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Path(team_id): Path<i32>,
+    Query(query): Query<SummaryQuery>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    require_team_role(&db, &user, team_id, TeamRole::Member).await?;
+
+    let user_ids = db
+        .team_handler()
+        .team_members(team_id)
+        .await?
+        .into_iter()
+        .map(|member| member.user_id)
+        .collect::<Vec<_>>();
+
+    let summary = summary_of(&db, &user, query, tz, Some(&user_ids)).await?;
+
+    Ok((StatusCode::OK, Json(json!(summary))))
+}
@@ -0,0 +1,142 @@
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::AuthUser;
+use crate::db::totp::TotpHandlerTrait;
+use crate::db::DB;
+use crate::error::{AppError, Result};
+use crate::login_throttle;
+use crate::password;
+use crate::totp;
+use crate::util::timezone::{into_timezone, TimeZoneQuery};
+
+/// Whether two-factor authentication is enabled for the current user.
+pub async fn totp_status(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+
+    let credential = db.totp_handler().totp_credential(user.id).await?;
+    let confirmed_at = credential
+        .as_ref()
+        .and_then(|credential| credential.confirmed_at);
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "enabled": confirmed_at.is_some(),
+            "confirmed_at": confirmed_at.map(|confirmed_at| into_timezone(confirmed_at, tz)),
+            "recovery_codes_remaining": credential
+                .filter(|credential| credential.confirmed_at.is_some())
+                .map_or(0, |credential| credential.recovery_codes_remaining),
+        })),
+    ))
+}
+
+/// Starts the enrollment with a new secret. It takes effect once confirmed with a code.
+/// Calling this again before confirming replaces the secret.
+pub async fn begin_totp_enrollment(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+) -> Result<impl IntoResponse> {
+    let secret = totp::generate_secret()?;
+
+    db.totp_handler().begin_enrollment(user.id, &secret).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "secret": secret,
+            "provisioning_uri": totp::provisioning_uri(&secret, &user.username),
+        })),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TotpConfirmPayload {
+    pub code: String,
+}
+
+/// Enables two-factor authentication with the first code from the authenticator app.
+/// The recovery codes are shown only in this response.
+pub async fn confirm_totp_enrollment(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Json(payload): Json<TotpConfirmPayload>,
+) -> Result<impl IntoResponse> {
+    let Some(credential) = db.totp_handler().totp_credential(user.id).await? else {
+        return Err(AppError::NotFound(String::from(
+            "Two-factor authentication enrollment not started",
+        )));
+    };
+    if credential.confirmed_at.is_some() {
+        return Err(AppError::Conflict(String::from(
+            "Two-factor authentication is already enabled",
+        )));
+    }
+
+    let Some(step) = totp::verify(
+        &credential.secret,
+        &payload.code,
+        Utc::now().naive_utc(),
+        None,
+    ) else {
+        return Err(AppError::Validation(String::from("Invalid code")));
+    };
+
+    let (recovery_codes, recovery_code_hashes) = totp::generate_recovery_codes()?;
+
+    db.totp_handler()
+        .confirm_enrollment(user.id, step, &recovery_code_hashes)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Two-factor authentication enabled",
+            "recovery_codes": recovery_codes,
+        })),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TotpDisablePayload {
+    pub password: String,
+}
+
+/// Turns two-factor authentication off. Requires the password, not only the session.
+pub async fn disable_totp(
+    State(db): State<DB>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthUser { user, .. }: AuthUser,
+    Json(payload): Json<TotpDisablePayload>,
+) -> Result<impl IntoResponse> {
+    // パスワードの確認はログインと同じ回数で止める。
+    let ip = addr.ip().to_string();
+    login_throttle::ensure_not_locked(&db, &user.username, &ip).await?;
+
+    let is_valid = password::verify(&user.password, &user.salt, &payload.password)?.is_valid();
+    if !is_valid {
+        login_throttle::record_failure(&db, &user.username, &ip).await?;
+        return Err(AppError::Forbidden(String::from("Password is incorrect")));
+    }
+    login_throttle::record_success(&db, &user.username).await?;
+
+    db.totp_handler().disable_totp(user.id, user.id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Two-factor authentication disabled",
+        })),
+    ))
+}
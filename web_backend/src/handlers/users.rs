@@ -1,33 +1,153 @@
-use axum::extract::{Query, State};
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, Query, State};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
+use serde::Deserialize;
 use serde_json::json;
 
-use crate::db::user::User;
+use crate::auth::AuthUser;
+use crate::db::user::{User, UserFilter, UserStatus, Visibility};
 use crate::db::{user::UserHandlerTrait, DB};
-use crate::error::Result;
-use crate::get_user_by_session_token;
-
-use super::gongzuo::{session_token_invalid_error, SessionQuery};
+use crate::error::{AppError, Result};
+use crate::login_throttle;
+use crate::password;
+use crate::util::timezone::{parse_time_zone, TimeZoneQuery};
 
-pub async fn users(State(db): State<DB>) -> Result<impl IntoResponse> {
-    let users: Vec<_> = db
+pub async fn users(
+    State(db): State<DB>,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let users = db
         .user_handler()
-        .users()
+        .users(UserFilter {
+            include_admins: false,
+            status: Some(UserStatus::Active),
+        })
         .await?
         .into_iter()
-        .filter(|user| !user.is_admin)
-        .map(User::from)
-        .collect();
+        .map(|user| {
+            // viewer がいないので、指定がなければ各ユーザー自身の time zone で表示する。
+            let tz = tz.resolve(&user.time_zone)?;
+            Ok(User::from_summary(user, tz))
+        })
+        .collect::<Result<Vec<_>>>()?;
     Ok(Json(users))
 }
 
 pub async fn me(
+    AuthUser { user, .. }: AuthUser,
+    Query(tz): Query<TimeZoneQuery>,
+) -> Result<impl IntoResponse> {
+    let tz = tz.resolve(&user.time_zone)?;
+    Ok((StatusCode::OK, Json(json!(User::from_raw(user, tz)))))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TimeZonePayload {
+    pub time_zone: String,
+}
+
+pub async fn update_time_zone(
     State(db): State<DB>,
-    Query(SessionQuery { session_token }): Query<SessionQuery>,
+    AuthUser { user, .. }: AuthUser,
+    Json(payload): Json<TimeZonePayload>,
 ) -> Result<impl IntoResponse> {
-    let user = get_user_by_session_token!(db, session_token);
+    let TimeZonePayload { time_zone } = payload;
+
+    let tz = parse_time_zone(&time_zone)?;
+
+    db.user_handler()
+        .update_time_zone(user.id, tz.name())
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Time zone updated",
+            "time_zone": tz.name(),
+        })),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct VisibilityPayload {
+    pub visibility: Visibility,
+}
+
+/// Changes who can read the gongzuos of the user.
+pub async fn update_visibility(
+    State(db): State<DB>,
+    AuthUser { user, .. }: AuthUser,
+    Json(payload): Json<VisibilityPayload>,
+) -> Result<impl IntoResponse> {
+    let VisibilityPayload { visibility } = payload;
+
+    db.user_handler()
+        .update_visibility(user.id, visibility)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Visibility updated",
+            "visibility": visibility,
+        })),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PasswordChangePayload {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Changes the password of the current user, logs out the other sessions
+/// and revokes the access tokens.
+pub async fn change_password(
+    State(db): State<DB>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    AuthUser {
+        user,
+        session_token,
+    }: AuthUser,
+    Json(payload): Json<PasswordChangePayload>,
+) -> Result<impl IntoResponse> {
+    let PasswordChangePayload {
+        current_password,
+        new_password,
+    } = payload;
+
+    // 盗まれたセッションからの総当たりも、ログインと同じ回数で止める。
+    let ip = addr.ip().to_string();
+    login_throttle::ensure_not_locked(&db, &user.username, &ip).await?;
+
+    let is_valid = password::verify(&user.password, &user.salt, &current_password)?.is_valid();
+    if !is_valid {
+        login_throttle::record_failure(&db, &user.username, &ip).await?;
+        return Err(AppError::Forbidden(String::from(
+            "Current password is incorrect",
+        )));
+    }
+    login_throttle::record_success(&db, &user.username).await?;
+
+    if new_password.is_empty() {
+        return Err(AppError::Validation(String::from(
+            "new_password must not be empty",
+        )));
+    }
+
+    let password_hash = password::hash(&new_password)?;
+
+    db.user_handler()
+        .change_password(user.id, &password_hash, &session_token)
+        .await?;
 
-    Ok((StatusCode::OK, Json(json!(User::from(user)))))
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Password changed",
+        })),
+    ))
 }
@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use once_cell::sync::Lazy;
+
+use crate::db::login_failure::{LoginFailureHandlerTrait, LoginScope};
+use crate::db::DB;
+use crate::error::{AppError, Result};
+
+const DEFAULT_MAX_FAILURES_PER_USERNAME: i32 = 5;
+const DEFAULT_MAX_FAILURES_PER_IP: i32 = 20;
+const DEFAULT_LOCKOUT_SECONDS: i64 = 30;
+const DEFAULT_MAX_LOCKOUT_SECONDS: i64 = 60 * 60;
+
+/// Failures are counted from zero again after this long without any.
+fn failure_window() -> Duration {
+    Duration::days(1)
+}
+
+fn env_or<T: FromStr + PartialOrd + From<u8>>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .map_or(None, |value| value.parse().ok())
+        .filter(|value| *value > T::from(0))
+        .unwrap_or(default)
+}
+
+/// Failed logins in a row allowed for a username before it is locked.
+/// Set with `LOGIN_MAX_FAILURES_PER_USERNAME`.
+static MAX_FAILURES_PER_USERNAME: Lazy<i32> = Lazy::new(|| {
+    env_or(
+        "LOGIN_MAX_FAILURES_PER_USERNAME",
+        DEFAULT_MAX_FAILURES_PER_USERNAME,
+    )
+});
+
+/// Failed logins in a row allowed from an IP address, whatever the username.
+/// Larger than the one for a username since users may share an address.
+/// Set with `LOGIN_MAX_FAILURES_PER_IP`.
+static MAX_FAILURES_PER_IP: Lazy<i32> =
+    Lazy::new(|| env_or("LOGIN_MAX_FAILURES_PER_IP", DEFAULT_MAX_FAILURES_PER_IP));
+
+/// The first lockout, which doubles with every further failure.
+/// Set with `LOGIN_LOCKOUT_SECONDS`.
+static LOCKOUT_SECONDS: Lazy<i64> =
+    Lazy::new(|| env_or("LOGIN_LOCKOUT_SECONDS", DEFAULT_LOCKOUT_SECONDS));
+
+/// Set with `LOGIN_MAX_LOCKOUT_SECONDS`.
+static MAX_LOCKOUT_SECONDS: Lazy<i64> =
+    Lazy::new(|| env_or("LOGIN_MAX_LOCKOUT_SECONDS", DEFAULT_MAX_LOCKOUT_SECONDS));
+
+fn max_failures(scope: LoginScope) -> i32 {
+    match scope {
+        LoginScope::Username => *MAX_FAILURES_PER_USERNAME,
+        LoginScope::Ip => *MAX_FAILURES_PER_IP,
+    }
+}
+
+/// How long to lock after `failed_count` failures in a row, if at all.
+fn lockout(scope: LoginScope, failed_count: i32) -> Option<Duration> {
+    let exceeded = failed_count - max_failures(scope);
+    if exceeded < 0 {
+        return None;
+    }
+
+    let seconds = LOCKOUT_SECONDS
+        .saturating_mul(1_i64 << exceeded.min(32))
+        .min(*MAX_LOCKOUT_SECONDS);
+    Some(Duration::seconds(seconds))
+}
+
+/// Rejects the login before the password is checked
+/// while either the username or the IP address is locked.
+pub async fn ensure_not_locked(db: &DB, username: &str, ip: &str) -> Result<()> {
+    let now = Utc::now().naive_utc();
+    let Some(locked_until) = db
+        .login_failure_handler()
+        .locked_until(username, ip, now)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    Err(too_many_requests(locked_until, now))
+}
+
+/// Counts the failure for both the username and the IP address, and locks them if there are too many.
+pub async fn record_failure(db: &DB, username: &str, ip: &str) -> Result<()> {
+    let now = Utc::now().naive_utc();
+
+    for (scope, key) in [(LoginScope::Username, username), (LoginScope::Ip, ip)] {
+        let failed_count = db
+            .login_failure_handler()
+            .record_failure(scope, key, now, now - failure_window())
+            .await?;
+
+        if let Some(lockout) = lockout(scope, failed_count) {
+            db.login_failure_handler()
+                .lock(scope, key, now + lockout)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Failures from the IP address are kept,
+/// so that one known password doesn't let the address try others.
+pub async fn record_success(db: &DB, username: &str) -> Result<()> {
+    db.login_failure_handler()
+        .clear(LoginScope::Username, username)
+        .await
+}
+
+fn too_many_requests(locked_until: NaiveDateTime, now: NaiveDateTime) -> AppError {
+    // 切り上げて、Retry-After の後には確実にロックが解けているようにする。
+    let retry_after_seconds = ((locked_until - now).num_milliseconds() + 999) / 1000;
+
+    AppError::TooManyRequests {
+        message: String::from("Too many failed logins. Try again later"),
+        retry_after_seconds,
+    }
+}
@@ -1,9 +1,16 @@
+pub mod access;
+pub mod auth;
+pub mod billing;
 pub mod db;
 pub mod error;
 pub mod handlers;
+pub mod login_throttle;
 pub mod password;
+pub mod report;
 pub mod router;
 pub mod session;
+pub mod totp;
+pub mod trash;
 pub mod util;
 
 use std::{
@@ -28,6 +35,8 @@ static DATABASE_URL: Lazy<String> = Lazy::new(|| {
 
 #[tokio::main]
 async fn main() {
+    tracing_subscriber::fmt::init();
+
     println!("Hello, world!");
 
     let pool = PgPoolOptions::new()
@@ -47,6 +56,8 @@ async fn main() {
         panic!("Admin user is not registered");
     }
 
+    tokio::spawn(trash::purge_trash_periodically(db.clone()));
+
     let app = app_router(db);
 
     let port = std::env::var("PORT")
@@ -62,18 +73,16 @@ async fn main() {
 
     if tls {
         let config = RustlsConfig::from_pem_file(
-            std::env::var("CERT_PATH")
-                .map(|path| PathBuf::from(path))
-                .unwrap_or(
-                    PathBuf::from("/")
-                        .join("etc")
-                        .join("letsencrypt")
-                        .join("live")
-                        .join("lemolatoon.ddns.net")
-                        .join("fullchain.pem"),
-                ),
+            std::env::var("CERT_PATH").map(PathBuf::from).unwrap_or(
+                PathBuf::from("/")
+                    .join("etc")
+                    .join("letsencrypt")
+                    .join("live")
+                    .join("lemolatoon.ddns.net")
+                    .join("fullchain.pem"),
+            ),
             std::env::var("PRIVATE_KEY_PATH")
-                .map(|path| PathBuf::from(path))
+                .map(PathBuf::from)
                 .unwrap_or(
                     PathBuf::from("/")
                         .join("etc")
@@ -87,7 +96,7 @@ async fn main() {
         .unwrap();
         println!("Listening on {}", &addr);
         axum_server::bind_rustls(addr, config)
-            .serve(app.into_make_service())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await
             .unwrap();
         return;
@@ -96,7 +105,7 @@ async fn main() {
     // run it with hyper
     println!("Listening on {}", &addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -1,43 +1,242 @@
-use ring::pbkdf2::{self, verify, PBKDF2_HMAC_SHA256};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chrono::Duration;
+use once_cell::sync::Lazy;
+use ring::digest::{digest, SHA256};
+use ring::pbkdf2::{self, PBKDF2_HMAC_SHA256};
 use ring::rand::SecureRandom;
 use ring::rand::SystemRandom;
 
-/// Derive a password using PBKDF2
-/// Returns a tuple of (salt, hashed_password)
-pub fn derive(password: String) -> anyhow::Result<(String, String)> {
+/// Iterations of the PBKDF2 hashes stored before Argon2id was introduced.
+const LEGACY_PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Cost of Argon2id for new hashes, set with `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS`
+/// and `ARGON2_PARALLELISM`. Hashes made with other parameters are rehashed on login,
+/// so the cost can be raised by changing them.
+static ARGON2_PARAMS: Lazy<Params> = Lazy::new(|| {
+    let param = |name: &str, default: u32| {
+        std::env::var(name)
+            .map_or(None, |value| value.parse().ok())
+            .unwrap_or(default)
+    };
+
+    Params::new(
+        param("ARGON2_MEMORY_KIB", Params::DEFAULT_M_COST),
+        param("ARGON2_ITERATIONS", Params::DEFAULT_T_COST),
+        param("ARGON2_PARALLELISM", Params::DEFAULT_P_COST),
+        None,
+    )
+    .expect("Invalid Argon2 parameters")
+});
+
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, ARGON2_PARAMS.clone())
+}
+
+/// Hashes a password with Argon2id.
+/// Returns a PHC string, which records the algorithm, the parameters and the salt.
+pub fn hash(password: &str) -> anyhow::Result<String> {
     let mut salt = [0u8; 16];
     SystemRandom::new()
         .fill(&mut salt)
         .map_err(|e| anyhow::anyhow!(e))?;
-    let mut hashed_password = [0u8; 32];
-    pbkdf2::derive(
-        PBKDF2_HMAC_SHA256,
-        std::num::NonZeroU32::new(100_000).unwrap(),
-        &salt,
-        password.as_bytes(),
-        &mut hashed_password,
-    );
-    let salt = hex::encode(salt);
-    let hashed_password = hex::encode(hashed_password);
+    let salt = SaltString::encode_b64(&salt).map_err(|e| anyhow::anyhow!(e))?;
+
+    let password_hash = argon2()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    Ok(password_hash.to_string())
+}
 
-    Ok((salt, hashed_password))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid {
+        /// The stored hash is legacy PBKDF2 or uses other Argon2 parameters,
+        /// so it should be replaced with [`hash`] of the password.
+        needs_rehash: bool,
+    },
 }
 
-/// Verify a password using PBKDF2
-pub fn verify_with_salt(
-    salt: String,
-    hashed_password: String,
-    password: String,
-) -> anyhow::Result<bool> {
+impl Verification {
+    pub fn is_valid(self) -> bool {
+        matches!(self, Verification::Valid { .. })
+    }
+}
+
+/// Verifies a password against `stored`, which is either a PHC string
+/// or a legacy hex PBKDF2 hash with its hex `salt`.
+pub fn verify(stored: &str, salt: &str, password: &str) -> anyhow::Result<Verification> {
+    if !stored.starts_with('$') {
+        return verify_legacy_pbkdf2(stored, salt, password);
+    }
+
+    let password_hash = PasswordHash::new(stored).map_err(|e| anyhow::anyhow!(e))?;
+
+    // 検証は保存されているパラメーターで行う。
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &password_hash)
+        .is_err()
+    {
+        return Ok(Verification::Invalid);
+    }
+
+    // PHC 文字列から読んだ Params には output_len が入るので、コストだけを比べる。
+    let needs_rehash = password_hash.algorithm != Algorithm::Argon2id.ident()
+        || Params::try_from(&password_hash).map_or(true, |params| {
+            (params.m_cost(), params.t_cost(), params.p_cost())
+                != (
+                    ARGON2_PARAMS.m_cost(),
+                    ARGON2_PARAMS.t_cost(),
+                    ARGON2_PARAMS.p_cost(),
+                )
+        });
+
+    Ok(Verification::Valid { needs_rehash })
+}
+
+/// Hash of no one's password. Verified when the username is unknown,
+/// so that the response time doesn't tell whether the user exists.
+static DUMMY_HASH: Lazy<String> =
+    Lazy::new(|| hash("dummy password").expect("Failed to hash the dummy password"));
+
+/// Spends as long as `verify` does for an existing user. The result is always invalid.
+pub fn verify_dummy(password: &str) -> anyhow::Result<Verification> {
+    verify(&DUMMY_HASH, "", password)?;
+    Ok(Verification::Invalid)
+}
+
+fn verify_legacy_pbkdf2(stored: &str, salt: &str, password: &str) -> anyhow::Result<Verification> {
     let salt = hex::decode(salt)?;
-    let hashed_password = hex::decode(hashed_password)?;
+    let hashed_password = hex::decode(stored)?;
 
-    Ok(verify(
+    let is_valid = pbkdf2::verify(
         PBKDF2_HMAC_SHA256,
-        std::num::NonZeroU32::new(100_000).unwrap(),
+        std::num::NonZeroU32::new(LEGACY_PBKDF2_ITERATIONS).unwrap(),
         &salt,
         password.as_bytes(),
         &hashed_password,
     )
-    .is_ok())
+    .is_ok();
+
+    Ok(if is_valid {
+        Verification::Valid { needs_rehash: true }
+    } else {
+        Verification::Invalid
+    })
+}
+
+/// How long a password reset token can be used after it is issued.
+pub fn reset_token_ttl() -> Duration {
+    Duration::hours(24)
+}
+
+/// Prefix of personal access tokens, which tells them apart from session tokens.
+pub const ACCESS_TOKEN_PREFIX: &str = "gzp_";
+
+fn random_token() -> anyhow::Result<String> {
+    let mut token = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut token)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(hex::encode(token))
+}
+
+/// Creates a random token to hand out once.
+/// Returns a tuple of (token, token_hash); only the hash should be stored.
+pub fn create_token() -> anyhow::Result<(String, String)> {
+    let token = random_token()?;
+    let token_hash = hash_token(&token);
+
+    Ok((token, token_hash))
+}
+
+/// Same as [`create_token`], but the token starts with [`ACCESS_TOKEN_PREFIX`].
+pub fn create_access_token() -> anyhow::Result<(String, String)> {
+    let token = format!("{}{}", ACCESS_TOKEN_PREFIX, random_token()?);
+    let token_hash = hash_token(&token);
+
+    Ok((token, token_hash))
+}
+
+/// SHA-256 of a token in hex. Tokens are random enough that they don't need a salt.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(digest(&SHA256, token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phc_hash_round_trip() {
+        let stored = hash("correct horse").unwrap();
+        assert!(stored.starts_with("$argon2id$"));
+
+        assert_eq!(
+            verify(&stored, "", "correct horse").unwrap(),
+            Verification::Valid {
+                needs_rehash: false
+            }
+        );
+        assert_eq!(
+            verify(&stored, "", "wrong horse").unwrap(),
+            Verification::Invalid
+        );
+    }
+
+    #[test]
+    fn legacy_pbkdf2_hash_is_verified_and_needs_rehash() {
+        let salt = [7u8; 16];
+        let mut hashed_password = [0u8; 32];
+        pbkdf2::derive(
+            PBKDF2_HMAC_SHA256,
+            std::num::NonZeroU32::new(LEGACY_PBKDF2_ITERATIONS).unwrap(),
+            &salt,
+            b"correct horse",
+            &mut hashed_password,
+        );
+        let stored = hex::encode(hashed_password);
+        let salt = hex::encode(salt);
+
+        assert_eq!(
+            verify(&stored, &salt, "correct horse").unwrap(),
+            Verification::Valid { needs_rehash: true }
+        );
+        assert_eq!(
+            verify(&stored, &salt, "wrong horse").unwrap(),
+            Verification::Invalid
+        );
+    }
+
+    #[test]
+    fn hash_with_other_params_needs_rehash() {
+        let params = Params::new(8, 1, 1, None).unwrap();
+        assert_ne!(params, *ARGON2_PARAMS);
+        let salt = SaltString::encode_b64(&[1u8; 16]).unwrap();
+        let stored = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(b"correct horse", &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            verify(&stored, "", "correct horse").unwrap(),
+            Verification::Valid { needs_rehash: true }
+        );
+    }
+
+    #[test]
+    fn hash_with_other_algorithm_needs_rehash() {
+        let salt = SaltString::encode_b64(&[1u8; 16]).unwrap();
+        let stored = Argon2::new(Algorithm::Argon2i, Version::V0x13, ARGON2_PARAMS.clone())
+            .hash_password(b"correct horse", &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            verify(&stored, "", "correct horse").unwrap(),
+            Verification::Valid { needs_rehash: true }
+        );
+    }
 }
@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, Months, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::db::gongzuo::GongzuoRaw;
+use crate::db::project::Project;
+use crate::util::timezone::into_timezone;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    /// Weeks start on Monday.
+    Week,
+    Month,
+}
+
+impl Granularity {
+    /// The first day of the bucket which contains `date`.
+    fn bucket_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => date,
+            Granularity::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Granularity::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    fn next_bucket_start(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => start + Days::new(1),
+            Granularity::Week => start + Days::new(7),
+            Granularity::Month => start + Months::new(1),
+        }
+    }
+}
+
+/// The UTC instant at which `date` begins in `tz`.
+/// If midnight does not exist because of a DST transition, the day begins at the first
+/// instant after the gap.
+pub fn start_of_day(date: NaiveDate, tz: Tz) -> NaiveDateTime {
+    let mut local = date.and_hms_opt(0, 0, 0).unwrap();
+    loop {
+        if let Some(datetime) = tz.from_local_datetime(&local).earliest() {
+            return datetime.naive_utc();
+        }
+        local += chrono::Duration::minutes(15);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct Durations {
+    pub work_seconds: i64,
+    pub not_work_seconds: i64,
+}
+
+impl Durations {
+    fn add(&mut self, counts_as_work: bool, seconds: i64) {
+        if counts_as_work {
+            self.work_seconds += seconds;
+        } else {
+            self.not_work_seconds += seconds;
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ContentSummary {
+    pub content_id: i32,
+    pub category_id: i32,
+    pub content: String,
+    pub seconds: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CategorySummary {
+    pub category_id: i32,
+    pub category_name: String,
+    pub counts_as_work: bool,
+    pub seconds: i64,
+}
+
+/// A gongzuo with several tags counts toward each of them.
+#[derive(Serialize, Debug, Clone)]
+pub struct TagSummary {
+    pub tag: String,
+    pub seconds: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UserSummary {
+    pub user_id: i32,
+    #[serde(flatten)]
+    pub durations: Durations,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Bucket {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+    #[serde(flatten)]
+    pub total: Durations,
+    pub by_category: Vec<CategorySummary>,
+    pub by_content: Vec<ContentSummary>,
+    pub by_tag: Vec<TagSummary>,
+    pub by_user: Vec<UserSummary>,
+}
+
+/// Time spent on a project. Gongzuos without a project are summed up with `project_id: None`.
+#[derive(Serialize, Debug, Clone)]
+pub struct ProjectSummary {
+    pub project_id: Option<i32>,
+    pub project_name: Option<String>,
+    pub client_id: Option<i32>,
+    pub client_name: Option<String>,
+    #[serde(flatten)]
+    pub total: Durations,
+    pub by_user: Vec<UserSummary>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Summary {
+    pub granularity: Granularity,
+    pub time_zone: String,
+    #[serde(flatten)]
+    pub total: Durations,
+    pub buckets: Vec<Bucket>,
+}
+
+/// Seconds the gongzuo was worked on within `[start, end)`. Breaks are not counted.
+fn seconds_within(
+    gongzuo: &GongzuoRaw,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    now: NaiveDateTime,
+) -> i64 {
+    gongzuo
+        .worked_periods(now)
+        .into_iter()
+        .map(|(started_at, ended_at)| {
+            let started_at = started_at.max(start);
+            let ended_at = ended_at.min(end);
+            (ended_at - started_at).num_seconds().max(0)
+        })
+        .sum()
+}
+
+/// Sums up `gongzuos` into buckets of `granularity` covering the dates `from..=to` in `tz`.
+/// A gongzuo which crosses a bucket boundary is split between the buckets.
+pub fn summarize(
+    gongzuos: &[GongzuoRaw],
+    granularity: Granularity,
+    from: NaiveDate,
+    to: NaiveDate,
+    tz: Tz,
+    now: NaiveDateTime,
+) -> Summary {
+    // Buckets follow the calendar, but the first and the last one are cut at `from` and `to`.
+    let range_end = to + Days::new(1);
+    let mut boundaries = vec![from];
+    let mut date = granularity.next_bucket_start(granularity.bucket_start(from));
+    while date < range_end {
+        boundaries.push(date);
+        date = granularity.next_bucket_start(date);
+    }
+    boundaries.push(range_end);
+
+    let boundaries = boundaries
+        .into_iter()
+        .map(|date| start_of_day(date, tz))
+        .collect::<Vec<_>>();
+
+    let mut total = Durations::default();
+    let buckets = boundaries
+        .windows(2)
+        .map(|window| {
+            let (bucket_start, bucket_end) = (window[0], window[1]);
+
+            let mut bucket_total = Durations::default();
+            let mut by_category = BTreeMap::<i32, CategorySummary>::new();
+            let mut by_content = BTreeMap::<i32, ContentSummary>::new();
+            let mut by_tag = BTreeMap::<&str, i64>::new();
+            let mut by_user = BTreeMap::<i32, Durations>::new();
+
+            for gongzuo in gongzuos {
+                let seconds = seconds_within(gongzuo, bucket_start, bucket_end, now);
+
+                if seconds == 0 {
+                    continue;
+                }
+
+                bucket_total.add(gongzuo.counts_as_work, seconds);
+                by_user
+                    .entry(gongzuo.user_id)
+                    .or_default()
+                    .add(gongzuo.counts_as_work, seconds);
+                by_category
+                    .entry(gongzuo.category_id)
+                    .or_insert_with(|| CategorySummary {
+                        category_id: gongzuo.category_id,
+                        category_name: gongzuo.category_name.clone(),
+                        counts_as_work: gongzuo.counts_as_work,
+                        seconds: 0,
+                    })
+                    .seconds += seconds;
+                by_content
+                    .entry(gongzuo.content_id)
+                    .or_insert_with(|| ContentSummary {
+                        content_id: gongzuo.content_id,
+                        category_id: gongzuo.category_id,
+                        content: gongzuo.content.clone(),
+                        seconds: 0,
+                    })
+                    .seconds += seconds;
+                for tag in &gongzuo.tags {
+                    *by_tag.entry(tag).or_default() += seconds;
+                }
+            }
+
+            total.work_seconds += bucket_total.work_seconds;
+            total.not_work_seconds += bucket_total.not_work_seconds;
+
+            Bucket {
+                start: into_timezone(bucket_start, tz),
+                end: into_timezone(bucket_end, tz),
+                total: bucket_total,
+                by_category: by_category.into_values().collect(),
+                by_content: by_content.into_values().collect(),
+                by_tag: by_tag
+                    .into_iter()
+                    .map(|(tag, seconds)| TagSummary {
+                        tag: tag.to_string(),
+                        seconds,
+                    })
+                    .collect(),
+                by_user: by_user
+                    .into_iter()
+                    .map(|(user_id, durations)| UserSummary { user_id, durations })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Summary {
+        granularity,
+        time_zone: tz.name().to_string(),
+        total,
+        buckets,
+    }
+}
+
+/// Sums up `gongzuos` within `[from, to)` per project. `projects` is used to look up names.
+pub fn summarize_projects(
+    gongzuos: &[GongzuoRaw],
+    projects: &[Project],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    now: NaiveDateTime,
+) -> Vec<ProjectSummary> {
+    let mut totals = BTreeMap::<Option<i32>, (Durations, BTreeMap<i32, Durations>)>::new();
+
+    for gongzuo in gongzuos {
+        let seconds = seconds_within(gongzuo, from, to, now);
+        if seconds == 0 {
+            continue;
+        }
+
+        let (total, by_user) = totals.entry(gongzuo.project_id).or_default();
+        total.add(gongzuo.counts_as_work, seconds);
+        by_user
+            .entry(gongzuo.user_id)
+            .or_default()
+            .add(gongzuo.counts_as_work, seconds);
+    }
+
+    totals
+        .into_iter()
+        .map(|(project_id, (total, by_user))| {
+            let project = project_id
+                .and_then(|project_id| projects.iter().find(|project| project.id == project_id));
+
+            ProjectSummary {
+                project_id,
+                project_name: project.map(|project| project.name.clone()),
+                client_id: project.map(|project| project.client_id),
+                client_name: project.map(|project| project.client_name.clone()),
+                total,
+                by_user: by_user
+                    .into_iter()
+                    .map(|(user_id, durations)| UserSummary { user_id, durations })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::db::gongzuo::BreakRaw;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    /// A work gongzuo with UTC times.
+    fn gongzuo(started_at: &str, ended_at: Option<&str>, breaks: &[(&str, &str)]) -> GongzuoRaw {
+        GongzuoRaw {
+            id: 1,
+            user_id: 1,
+            content_id: 1,
+            started_at: at(started_at),
+            ended_at: ended_at.map(at),
+            category_id: 0,
+            category_name: String::from("Work"),
+            counts_as_work: true,
+            project_id: None,
+            project_name: None,
+            invoice_id: None,
+            deleted_at: None,
+            content: String::from("coding"),
+            tags: vec![String::from("review")],
+            breaks: Json(
+                breaks
+                    .iter()
+                    .map(|(started_at, ended_at)| BreakRaw {
+                        started_at: at(started_at),
+                        ended_at: Some(at(ended_at)),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn bucket_seconds(summary: &Summary) -> Vec<i64> {
+        summary
+            .buckets
+            .iter()
+            .map(|bucket| bucket.total.work_seconds)
+            .collect()
+    }
+
+    #[test]
+    fn splits_a_gongzuo_across_midnight() {
+        // 2024-01-01 23:00 から 2024-01-02 01:30 まで (Asia/Tokyo)
+        let gongzuos = [gongzuo("2024-01-01 14:00", Some("2024-01-01 16:30"), &[])];
+        let summary = summarize(
+            &gongzuos,
+            Granularity::Day,
+            date("2024-01-01"),
+            date("2024-01-02"),
+            chrono_tz::Asia::Tokyo,
+            at("2024-01-10 00:00"),
+        );
+
+        assert_eq!(bucket_seconds(&summary), [60 * 60, 90 * 60]);
+        assert_eq!(summary.total.work_seconds, 150 * 60);
+        assert_eq!(summary.buckets[1].by_tag[0].seconds, 90 * 60);
+    }
+
+    #[test]
+    fn subtracts_breaks() {
+        let gongzuos = [gongzuo(
+            "2024-01-01 14:00",
+            Some("2024-01-01 16:30"),
+            &[("2024-01-01 14:30", "2024-01-01 15:30")],
+        )];
+        let summary = summarize(
+            &gongzuos,
+            Granularity::Day,
+            date("2024-01-01"),
+            date("2024-01-02"),
+            chrono_tz::Asia::Tokyo,
+            at("2024-01-10 00:00"),
+        );
+
+        // 休憩は 23:30 から 00:30 まで
+        assert_eq!(bucket_seconds(&summary), [30 * 60, 60 * 60]);
+    }
+
+    #[test]
+    fn counts_an_ongoing_gongzuo_until_now() {
+        let gongzuos = [gongzuo("2024-01-01 14:00", None, &[])];
+        let summary = summarize(
+            &gongzuos,
+            Granularity::Day,
+            date("2024-01-01"),
+            date("2024-01-02"),
+            chrono_tz::Asia::Tokyo,
+            at("2024-01-01 15:00"),
+        );
+
+        assert_eq!(bucket_seconds(&summary), [60 * 60, 0]);
+    }
+
+    #[test]
+    fn days_follow_dst_transitions() {
+        // America/New_York は 2024-03-10 02:00 に EST (-05:00) から EDT (-04:00) になり、
+        // 2024-11-03 02:00 に戻る。その日は 23 時間と 25 時間になる
+        let tz = chrono_tz::America::New_York;
+        let gongzuos = [
+            gongzuo("2024-03-09 05:00", Some("2024-03-12 04:00"), &[]),
+            gongzuo("2024-11-02 04:00", Some("2024-11-05 05:00"), &[]),
+        ];
+
+        let spring = summarize(
+            &gongzuos,
+            Granularity::Day,
+            date("2024-03-09"),
+            date("2024-03-11"),
+            tz,
+            at("2024-12-01 00:00"),
+        );
+        assert_eq!(
+            bucket_seconds(&spring),
+            [24 * 60 * 60, 23 * 60 * 60, 24 * 60 * 60]
+        );
+
+        let fall = summarize(
+            &gongzuos,
+            Granularity::Day,
+            date("2024-11-02"),
+            date("2024-11-04"),
+            tz,
+            at("2024-12-01 00:00"),
+        );
+        assert_eq!(
+            bucket_seconds(&fall),
+            [24 * 60 * 60, 25 * 60 * 60, 24 * 60 * 60]
+        );
+    }
+
+    #[test]
+    fn a_day_without_midnight_starts_after_the_gap() {
+        // America/Santiago は 2024-09-08 00:00 (-04:00) に 01:00 (-03:00) へ進む
+        let tz = chrono_tz::America::Santiago;
+
+        assert_eq!(start_of_day(date("2024-09-07"), tz), at("2024-09-07 04:00"));
+        assert_eq!(start_of_day(date("2024-09-08"), tz), at("2024-09-08 04:00"));
+        assert_eq!(start_of_day(date("2024-09-09"), tz), at("2024-09-09 03:00"));
+    }
+
+    #[test]
+    fn weeks_start_on_monday_and_are_cut_at_the_range() {
+        let gongzuos = [
+            // 2024-01-07 (日) と 2024-01-08 (月) の 09:00 から 1 時間ずつ (UTC)
+            gongzuo("2024-01-07 09:00", Some("2024-01-07 10:00"), &[]),
+            gongzuo("2024-01-08 09:00", Some("2024-01-08 10:00"), &[]),
+        ];
+        let summary = summarize(
+            &gongzuos,
+            Granularity::Week,
+            date("2024-01-05"),
+            date("2024-01-09"),
+            chrono_tz::UTC,
+            at("2024-02-01 00:00"),
+        );
+
+        assert_eq!(summary.buckets.len(), 2);
+        assert_eq!(summary.buckets[0].start.naive_utc(), at("2024-01-05 00:00"));
+        assert_eq!(summary.buckets[1].start.naive_utc(), at("2024-01-08 00:00"));
+        assert_eq!(summary.buckets[1].end.naive_utc(), at("2024-01-10 00:00"));
+        assert_eq!(bucket_seconds(&summary), [60 * 60, 60 * 60]);
+    }
+}
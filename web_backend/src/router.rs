@@ -1,2 +1,14 @@
+pub mod access_tokens;
+pub mod admin;
+pub mod categories;
+pub mod clients;
 pub mod gongzuo;
+pub mod invitations;
+pub mod invoices;
+pub mod projects;
+pub mod rates;
+pub mod reports;
+pub mod roles;
 pub mod root;
+pub mod sessions;
+pub mod teams;
@@ -0,0 +1,16 @@
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn access_tokens_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::access_tokens::access_tokens)
+                .post(handlers::access_tokens::create_access_token),
+        )
+        .route("/:id", delete(handlers::access_tokens::revoke_access_token))
+}
@@ -0,0 +1,32 @@
+use axum::{
+    routing::{get, post, put},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn admin_router() -> Router<db::DB> {
+    Router::new()
+        .route("/users", get(handlers::admin::users))
+        .route(
+            "/users/:id/deactivate",
+            post(handlers::admin::deactivate_user),
+        )
+        .route(
+            "/users/:id/reactivate",
+            post(handlers::admin::reactivate_user),
+        )
+        .route("/users/:id/unlock", post(handlers::admin::unlock_user))
+        .route("/users/:id/totp_reset", post(handlers::admin::reset_totp))
+        .route(
+            "/users/:id/password_reset",
+            post(handlers::admin::reset_password),
+        )
+        .route(
+            "/users/:id/reset_token",
+            post(handlers::admin::issue_reset_token),
+        )
+        .route("/users/:id/username", put(handlers::admin::rename_user))
+        .route("/users/:id/admin", put(handlers::admin::update_admin))
+        .route("/audit_logs", get(handlers::admin::audit_logs))
+}
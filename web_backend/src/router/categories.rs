@@ -0,0 +1,19 @@
+use axum::{
+    routing::{get, put},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn categories_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::categories::categories).post(handlers::categories::create_category),
+        )
+        .route(
+            "/:id",
+            put(handlers::categories::update_category)
+                .delete(handlers::categories::delete_category),
+        )
+}
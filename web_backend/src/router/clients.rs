@@ -0,0 +1,18 @@
+use axum::{
+    routing::{get, put},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn clients_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::clients::clients).post(handlers::clients::create_client),
+        )
+        .route(
+            "/:id",
+            put(handlers::clients::update_client).delete(handlers::clients::delete_client),
+        )
+}
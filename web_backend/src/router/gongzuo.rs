@@ -3,15 +3,32 @@ use axum::{
     Router,
 };
 
-use crate::{db, handlers};
+use crate::{
+    auth::require_permission,
+    db::{self, role::Permission},
+    handlers,
+};
 
-pub fn gongzuo_router() -> Router<db::DB> {
+pub fn gongzuo_router(db: &db::DB) -> Router<db::DB> {
     Router::new()
         .route("/", get(|| async { "Hello, world! from '/gongzuo'" }))
         .route("/gongzuos", get(handlers::gongzuo::all_ongzuos))
+        .route("/current", get(handlers::gongzuo::current_gongzuo))
         .route("/delete", delete(handlers::gongzuo::delete_gongzuo))
         .route("/edit", put(handlers::gongzuo::edit_gongzuo))
         .route("/start", post(handlers::gongzuo::start_gongzuo))
         .route("/end", post(handlers::gongzuo::end_gongzuo))
-        .route("/:id", get(handlers::gongzuo::gongzuo_by_id))
+        .route("/switch", post(handlers::gongzuo::switch_gongzuo))
+        .route("/pause", post(handlers::gongzuo::pause_gongzuo))
+        .route("/resume", post(handlers::gongzuo::resume_gongzuo))
+        .route("/trash", get(handlers::gongzuo::trash))
+        .route(
+            "/:id",
+            get(handlers::gongzuo::gongzuo_by_id).merge(
+                put(handlers::gongzuo::update_gongzuo_by_id)
+                    .route_layer(require_permission(db, Permission::GongzuoEditOthers)),
+            ),
+        )
+        .route("/:id/history", get(handlers::gongzuo::gongzuo_history))
+        .route("/:id/restore", post(handlers::gongzuo::restore_gongzuo))
 }
@@ -0,0 +1,12 @@
+use axum::{
+    routing::{delete, post},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn invitations_router() -> Router<db::DB> {
+    Router::new()
+        .route("/:id", delete(handlers::teams::decline_invitation))
+        .route("/:id/accept", post(handlers::teams::accept_invitation))
+}
@@ -0,0 +1,16 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn invoices_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::invoices::invoices).post(handlers::invoices::issue_invoice),
+        )
+        .route("/preview", post(handlers::invoices::preview_invoice))
+        .route("/:id", get(handlers::invoices::invoice_by_id))
+}
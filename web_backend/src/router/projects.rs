@@ -0,0 +1,28 @@
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn projects_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::projects::projects).post(handlers::projects::create_project),
+        )
+        .route(
+            "/:id",
+            get(handlers::projects::project_by_id)
+                .put(handlers::projects::update_project)
+                .delete(handlers::projects::delete_project),
+        )
+        .route(
+            "/:id/members",
+            get(handlers::projects::project_members).post(handlers::projects::add_project_member),
+        )
+        .route(
+            "/:id/members/:user_id",
+            delete(handlers::projects::remove_project_member),
+        )
+}
@@ -0,0 +1,15 @@
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn rates_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::rates::rates).post(handlers::rates::create_rate),
+        )
+        .route("/:id", delete(handlers::rates::delete_rate))
+}
@@ -0,0 +1,9 @@
+use axum::{routing::get, Router};
+
+use crate::{db, handlers};
+
+pub fn reports_router() -> Router<db::DB> {
+    Router::new()
+        .route("/summary", get(handlers::reports::summary))
+        .route("/projects", get(handlers::reports::projects))
+}
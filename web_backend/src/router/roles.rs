@@ -0,0 +1,18 @@
+use axum::{
+    routing::{get, put},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn roles_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::roles::roles).post(handlers::roles::create_role),
+        )
+        .route(
+            "/:id",
+            put(handlers::roles::update_role).delete(handlers::roles::delete_role),
+        )
+}
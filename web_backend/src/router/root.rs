@@ -1,33 +1,91 @@
 use axum::{
-    http::{header::CONTENT_TYPE, HeaderValue, Method},
-    routing::{get, post},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderName, HeaderValue, Method,
+    },
+    routing::{get, post, put},
     Router,
 };
 use tower_http::cors::CorsLayer;
 
-use crate::{db, handlers, router};
+use crate::{
+    auth::{require_permission, ALLOWED_ORIGINS, CSRF_HEADER},
+    db::{self, role::Permission},
+    handlers, router,
+};
 
 pub fn app_router(db: db::DB) -> Router {
-    let allowed_orgins: Vec<HeaderValue> =
-        ["http://localhost:3000", "https://gongzuo-one.vercel.app"]
-            .into_iter()
-            .map(|o| o.parse().unwrap())
-            .collect();
+    let allowed_orgins: Vec<HeaderValue> = ALLOWED_ORIGINS
+        .into_iter()
+        .map(|o| o.parse().unwrap())
+        .collect();
 
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
         .allow_origin(allowed_orgins)
         .allow_credentials(true)
-        .allow_headers([CONTENT_TYPE]);
+        .allow_headers([
+            CONTENT_TYPE,
+            AUTHORIZATION,
+            HeaderName::from_static(CSRF_HEADER),
+        ]);
 
     Router::new()
         .route("/", get(|| async { "Hello, world! from '/'" }))
         .route("/users", get(handlers::users::users))
-        .route("/register", post(handlers::register::register))
+        .route(
+            "/users/:id/current",
+            get(handlers::gongzuo::current_gongzuo_by_user_id),
+        )
+        .route(
+            "/users/:id/roles",
+            get(handlers::roles::user_roles).put(handlers::roles::update_user_roles),
+        )
+        .route(
+            "/register",
+            post(handlers::register::register)
+                .route_layer(require_permission(&db, Permission::UsersCreate)),
+        )
         .route("/login", post(handlers::login::login))
+        .route("/login/totp", post(handlers::login::login_totp))
         .route("/logout", post(handlers::logout::logout))
+        .route(
+            "/password_reset",
+            post(handlers::password_reset::reset_password),
+        )
         .route("/me", get(handlers::users::me))
-        .nest("/gongzuo", router::gongzuo::gongzuo_router())
+        .route("/me/time_zone", put(handlers::users::update_time_zone))
+        .route("/me/visibility", put(handlers::users::update_visibility))
+        .route("/me/password", put(handlers::users::change_password))
+        .route(
+            "/me/totp",
+            get(handlers::totp::totp_status)
+                .post(handlers::totp::begin_totp_enrollment)
+                .delete(handlers::totp::disable_totp),
+        )
+        .route(
+            "/me/totp/confirm",
+            post(handlers::totp::confirm_totp_enrollment),
+        )
+        .route("/me/invitations", get(handlers::teams::my_invitations))
+        .route("/me/permissions", get(handlers::roles::my_permissions))
+        .route("/tags", get(handlers::tags::tags))
+        .nest("/admin", router::admin::admin_router())
+        .nest("/sessions", router::sessions::sessions_router())
+        .nest(
+            "/access_tokens",
+            router::access_tokens::access_tokens_router(),
+        )
+        .nest("/categories", router::categories::categories_router())
+        .nest("/clients", router::clients::clients_router())
+        .nest("/projects", router::projects::projects_router())
+        .nest("/rates", router::rates::rates_router())
+        .nest("/invoices", router::invoices::invoices_router())
+        .nest("/roles", router::roles::roles_router())
+        .nest("/teams", router::teams::teams_router(&db))
+        .nest("/invitations", router::invitations::invitations_router())
+        .nest("/gongzuo", router::gongzuo::gongzuo_router(&db))
+        .nest("/reports", router::reports::reports_router())
         .with_state(db)
         .layer(cors)
 }
@@ -0,0 +1,15 @@
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+
+use crate::{db, handlers};
+
+pub fn sessions_router() -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::sessions::sessions).delete(handlers::sessions::revoke_all_sessions),
+        )
+        .route("/:id", delete(handlers::sessions::revoke_session))
+}
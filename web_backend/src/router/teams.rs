@@ -0,0 +1,44 @@
+use axum::{
+    routing::{delete, get, put},
+    Router,
+};
+
+use crate::{
+    auth::require_permission,
+    db::{self, role::Permission},
+    handlers,
+};
+
+pub fn teams_router(db: &db::DB) -> Router<db::DB> {
+    Router::new()
+        .route(
+            "/",
+            get(handlers::teams::teams).post(handlers::teams::create_team),
+        )
+        .route(
+            "/:id",
+            get(handlers::teams::team_by_id)
+                .put(handlers::teams::rename_team)
+                .delete(handlers::teams::delete_team),
+        )
+        .route("/:id/members", get(handlers::teams::team_members))
+        .route(
+            "/:id/members/:user_id",
+            put(handlers::teams::update_team_member).delete(handlers::teams::remove_team_member),
+        )
+        .route(
+            "/:id/invitations",
+            get(handlers::teams::team_invitations).post(handlers::teams::invite_to_team),
+        )
+        .route(
+            "/:id/invitations/:invitation_id",
+            delete(handlers::teams::cancel_team_invitation),
+        )
+        .route("/:id/gongzuos", get(handlers::teams::team_gongzuos))
+        .route("/:id/now", get(handlers::teams::team_now))
+        .route(
+            "/:id/reports/summary",
+            get(handlers::teams::team_summary)
+                .route_layer(require_permission(db, Permission::ReportsViewTeam)),
+        )
+}
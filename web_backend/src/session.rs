@@ -1,5 +1,16 @@
+use chrono::{Duration, NaiveDateTime};
 use uuid::Uuid;
 
+/// How long a session stays valid after its last use.
+/// Every authenticated request slides the expiry forward by this amount.
+pub fn session_ttl() -> Duration {
+    Duration::days(30)
+}
+
 pub fn create_session_token() -> String {
     Uuid::new_v4().to_string()
 }
+
+pub fn session_expires_at(last_seen_at: NaiveDateTime) -> NaiveDateTime {
+    last_seen_at + session_ttl()
+}
@@ -0,0 +1,192 @@
+use chrono::{Duration, NaiveDateTime};
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::password::hash_token;
+
+/// Shown in authenticator apps next to the username.
+const ISSUER: &str = "gongzuo";
+
+/// Seconds a code is valid for. Authenticator apps assume 30 when the URI doesn't say.
+const PERIOD: i64 = 30;
+
+const DIGITS: u32 = 6;
+
+/// Codes of this many steps before and after now are also accepted, for clocks which are a bit off.
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// How long the challenge from the password step can be exchanged for a session.
+pub fn challenge_ttl() -> Duration {
+    Duration::minutes(5)
+}
+
+/// A random secret in Base32, 160 bits as RFC 4226 recommends.
+pub fn generate_secret() -> anyhow::Result<String> {
+    let mut secret = [0u8; 20];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(BASE32_NOPAD.encode(&secret))
+}
+
+/// `otpauth://` URI which authenticator apps read, usually from a QR code.
+pub fn provisioning_uri(secret: &str, username: &str) -> String {
+    let label = format!("{}:{}", ISSUER, username);
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        utf8_percent_encode(&label, NON_ALPHANUMERIC),
+        secret,
+        utf8_percent_encode(ISSUER, NON_ALPHANUMERIC),
+        DIGITS,
+        PERIOD
+    )
+}
+
+fn code_at(key: &hmac::Key, step: i64) -> u32 {
+    // RFC 4226 の dynamic truncation
+    let tag = hmac::sign(key, &step.to_be_bytes());
+    let tag = tag.as_ref();
+    let offset = (tag[tag.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        tag[offset] & 0x7f,
+        tag[offset + 1],
+        tag[offset + 2],
+        tag[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Returns the time step of the code if it is valid around `now` and newer than
+/// `last_used_step`. Store the step so that the same code can't be used twice.
+pub fn verify(
+    secret: &str,
+    code: &str,
+    now: NaiveDateTime,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &secret);
+
+    let current_step = now.and_utc().timestamp() / PERIOD;
+    (current_step - ALLOWED_DRIFT_STEPS..=current_step + ALLOWED_DRIFT_STEPS)
+        .filter(|step| last_used_step.is_none_or(|last_used_step| *step > last_used_step))
+        .find(|step| code_at(&key, *step) == code)
+}
+
+/// Creates recovery codes like `3f9a1-c07b2`.
+/// Returns a tuple of (codes, code_hashes); only the hashes should be stored.
+pub fn generate_recovery_codes() -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let random = SystemRandom::new();
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let mut code = [0u8; 5];
+        random.fill(&mut code).map_err(|e| anyhow::anyhow!(e))?;
+        let code = hex::encode(code);
+        codes.push(format!("{}-{}", &code[..5], &code[5..]));
+    }
+    let code_hashes = codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+    Ok((codes, code_hashes))
+}
+
+/// Hyphens, spaces and case don't matter.
+pub fn hash_recovery_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_token(&code)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    /// The SHA-1 secret of RFC 6238, "12345678901234567890", in Base32.
+    const RFC_6238_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn at(timestamp: i64) -> NaiveDateTime {
+        DateTime::from_timestamp(timestamp, 0).unwrap().naive_utc()
+    }
+
+    #[test]
+    fn rfc_6238_test_vectors() {
+        // RFC 6238 の付録 B の 8 桁のコードの下 6 桁
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        for (timestamp, code) in vectors {
+            assert_eq!(
+                verify(RFC_6238_SECRET, code, at(timestamp), None),
+                Some(timestamp / PERIOD),
+                "T = {}",
+                timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn one_step_of_drift_is_accepted() {
+        let step = 1234567890 / PERIOD;
+        assert_eq!(
+            verify(RFC_6238_SECRET, "005924", at((step - 1) * PERIOD), None),
+            Some(step)
+        );
+        assert_eq!(
+            verify(RFC_6238_SECRET, "005924", at((step + 1) * PERIOD), None),
+            Some(step)
+        );
+        assert_eq!(
+            verify(RFC_6238_SECRET, "005924", at((step - 2) * PERIOD), None),
+            None
+        );
+        assert_eq!(
+            verify(RFC_6238_SECRET, "005924", at((step + 2) * PERIOD), None),
+            None
+        );
+    }
+
+    #[test]
+    fn used_steps_are_rejected() {
+        let step = 1234567890 / PERIOD;
+        assert_eq!(
+            verify(RFC_6238_SECRET, "005924", at(1234567890), Some(step - 1)),
+            Some(step)
+        );
+        assert_eq!(
+            verify(RFC_6238_SECRET, "005924", at(1234567890), Some(step)),
+            None
+        );
+        assert_eq!(
+            verify(RFC_6238_SECRET, "005924", at(1234567890), Some(step + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        assert_eq!(verify(RFC_6238_SECRET, "05924", at(1234567890), None), None);
+        assert_eq!(
+            verify(RFC_6238_SECRET, "00592a", at(1234567890), None),
+            None
+        );
+        assert_eq!(verify("not base32!", "005924", at(1234567890), None), None);
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, Utc};
+use once_cell::sync::Lazy;
+
+use crate::db::gongzuo::GongzuoHandlerTrait;
+use crate::db::DB;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// How often the trash is checked for expired gongzuos.
+const PURGE_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+/// Days a deleted gongzuo stays in the trash before it is deleted for good.
+/// Set with `TRASH_RETENTION_DAYS`, which must be at least 1 so that a deletion can be undone.
+static RETENTION_DAYS: Lazy<i64> = Lazy::new(|| {
+    let Ok(value) = std::env::var("TRASH_RETENTION_DAYS") else {
+        return DEFAULT_RETENTION_DAYS;
+    };
+
+    match value.parse() {
+        Ok(days) if days >= 1 => days,
+        _ => {
+            tracing::warn!(
+                "TRASH_RETENTION_DAYS must be an integer of at least 1, but is {:?}. Using {} days",
+                value,
+                DEFAULT_RETENTION_DAYS
+            );
+            DEFAULT_RETENTION_DAYS
+        }
+    }
+});
+
+pub fn trash_retention() -> Duration {
+    Duration::days(*RETENTION_DAYS)
+}
+
+/// Purges expired gongzuos from the trash every `PURGE_INTERVAL`. Runs forever.
+pub async fn purge_trash_periodically(db: DB) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let deleted_before = Utc::now().naive_utc() - trash_retention();
+        match db
+            .gongzuo_handler()
+            .purge_deleted_gongzuos(deleted_before)
+            .await
+        {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged {} gongzuos from the trash", purged),
+            Err(error) => tracing::error!("Failed to purge the trash: {:?}", error),
+        }
+    }
+}
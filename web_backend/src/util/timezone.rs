@@ -1,7 +1,41 @@
 use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_with::NoneAsEmptyString;
 
-pub fn into_jst(utc: NaiveDateTime) -> DateTime<FixedOffset> {
+use crate::error::{AppError, Result};
+
+/// Time zone of users who have not chosen one.
+pub const DEFAULT_TIME_ZONE: Tz = chrono_tz::Asia::Tokyo;
+
+/// Converts a UTC timestamp stored in the DB into local time of `tz`.
+/// The offset is looked up per instant, so DST transitions are handled.
+pub fn into_timezone(utc: NaiveDateTime, tz: Tz) -> DateTime<FixedOffset> {
     let datetime: DateTime<Utc> = DateTime::from_naive_utc_and_offset(utc, Utc);
-    const JST: Option<FixedOffset> = FixedOffset::east_opt(9 * 3600);
-    datetime.with_timezone(&JST.unwrap())
+    datetime.with_timezone(&tz).fixed_offset()
+}
+
+/// Parses an IANA time zone name such as `Asia/Tokyo` or `Europe/Berlin`.
+pub fn parse_time_zone(name: &str) -> Result<Tz> {
+    name.parse()
+        .map_err(|_| AppError::Validation(format!("Unknown time zone: {}", name)))
+}
+
+/// Optional `tz` query parameter which overrides the user's time zone on read endpoints.
+#[serde_with::serde_as]
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TimeZoneQuery {
+    #[serde_as(as = "NoneAsEmptyString")]
+    pub tz: Option<String>,
+}
+
+impl TimeZoneQuery {
+    /// The `tz` override if given, otherwise `preferred` (usually the user's own time zone).
+    pub fn resolve(&self, preferred: &str) -> Result<Tz> {
+        match &self.tz {
+            Some(tz) => parse_time_zone(tz),
+            None => Ok(preferred.parse().unwrap_or(DEFAULT_TIME_ZONE)),
+        }
+    }
 }